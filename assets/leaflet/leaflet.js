@@ -0,0 +1,9 @@
+// Placeholder until `vendor.sh` replaces it with Leaflet 1.9.4: loads Leaflet from its CDN,
+// so the map only works with internet access.
+(() => {
+    const script = document.createElement("script");
+    script.src = "https://unpkg.com/leaflet@1.9.4/dist/leaflet.js";
+    script.integrity = "sha256-20nQCchB9co0qIjJZRGuk2/Z9VM+kNiyxNV1lvTlZBo=";
+    script.crossOrigin = "";
+    document.head.append(script);
+})();
@@ -0,0 +1,28 @@
+#!/bin/sh
+# Vendors Leaflet into this directory, so the map doesn't need a CDN on the campus Wi-Fi.
+# Only the script and the stylesheet are needed: the markers are `L.divIcon`s, so the
+# images of `leaflet.css` are never loaded.
+set -eu
+
+VERSION=1.9.4
+# The Subresource Integrity hashes published by Leaflet for this version.
+JS_SHA256=20nQCchB9co0qIjJZRGuk2/Z9VM+kNiyxNV1lvTlZBo=
+CSS_SHA256=p4NxAoJBhIIN+hmNHrzRCf9tD/miZyoHS5obTRR9BMY=
+
+cd "$(dirname "$0")"
+
+fetch() {
+    curl -fsSL "https://unpkg.com/leaflet@$VERSION/$1" -o "$2.tmp"
+    sha=$(openssl dgst -sha256 -binary "$2.tmp" | openssl base64 -A)
+    if [ "$sha" != "$3" ]; then
+        rm "$2.tmp"
+        echo "$1 doesn't match its published hash" >&2
+        exit 1
+    fi
+    mv "$2.tmp" "$2"
+}
+
+fetch dist/leaflet.js leaflet.js "$JS_SHA256"
+fetch dist/leaflet.css leaflet.css "$CSS_SHA256"
+curl -fsSL "https://unpkg.com/leaflet@$VERSION/LICENSE" -o LICENSE
+echo "Vendored Leaflet $VERSION"
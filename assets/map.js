@@ -0,0 +1,147 @@
+// Leaflet glue for the map of the POIs, called from the `LocationTracker` component.
+// The map is created once, and then only the markers are updated.
+window.warwickMap = {
+    map: null,
+    user: null,
+    accuracy: null,
+    // URLs of the bundled `position` and `heading` icons of the user.
+    icons: null,
+    heading: null,
+    // The POIs as sent by `setPois`, with their `marker` once the map exists.
+    pois: [],
+
+    create(id, latitude, longitude, accuracy, icons) {
+        if (this.map !== null) {
+            this.map.remove();
+        }
+        this.map = L.map(id).setView([latitude, longitude], 17);
+        // The server serves the tiles from its tile pack, or redirects to its tile server.
+        L.tileLayer("/tiles/{z}/{x}/{y}.png", {
+            maxZoom: 19,
+            attribution: '&copy; <a href="https://www.openstreetmap.org/copyright">OpenStreetMap</a>',
+        }).addTo(this.map);
+        this.icons = icons;
+        this.accuracy = L.circle([latitude, longitude], {
+            radius: accuracy,
+            color: "#2980b9",
+            weight: 1,
+            fillOpacity: 0.15,
+            interactive: false,
+        }).addTo(this.map);
+        this.user = L.marker([latitude, longitude], {
+            title: "Position",
+            icon: this.userIcon(),
+            zIndexOffset: 1000,
+        }).addTo(this.map);
+        this.setPois(this.pois);
+    },
+
+    // Replaces the POIs, each with `id`, `name`, `latitude`, `longitude`, `messages`,
+    // `visited`, and `new_messages`.
+    setPois(pois) {
+        for (const poi of this.pois) {
+            if (poi.marker) {
+                poi.marker.remove();
+            }
+        }
+        this.pois = pois;
+        if (this.map === null) {
+            return;
+        }
+        for (const poi of this.pois) {
+            poi.marker = L.marker([poi.latitude, poi.longitude], { title: poi.name })
+                .bindPopup("")
+                .addTo(this.map);
+        }
+        this.update();
+    },
+
+    // `heading` is in degrees clockwise from north, or null if the user doesn't move.
+    moveUser(latitude, longitude, accuracy, heading) {
+        if (this.user === null) {
+            return;
+        }
+        this.user.setLatLng([latitude, longitude]);
+        this.accuracy.setLatLng([latitude, longitude]);
+        this.accuracy.setRadius(accuracy);
+        if (heading !== this.heading) {
+            this.heading = heading;
+            this.user.setIcon(this.userIcon());
+        }
+        this.update();
+    },
+
+    userIcon() {
+        const img = document.createElement("img");
+        img.width = 32;
+        img.height = 32;
+        if (this.heading === null) {
+            img.src = this.icons.position;
+        } else {
+            img.src = this.icons.heading;
+            img.style.transform = `rotate(${this.heading}deg)`;
+        }
+        return L.divIcon({ html: img, className: "", iconSize: [32, 32] });
+    },
+
+    // Styles the markers, which depend on the position of the user.
+    update() {
+        if (this.user === null || this.pois.length === 0) {
+            return;
+        }
+        const position = this.user.getLatLng();
+        for (const poi of this.pois) {
+            poi.distance = position.distanceTo([poi.latitude, poi.longitude]);
+        }
+        const nearest = this.pois.reduce((a, b) => (b.distance < a.distance ? b : a));
+        for (const poi of this.pois) {
+            // The nearest POI gets a ring, so its own state is still shown.
+            const state = poi.new_messages ? "new-messages"
+                : poi.visited ? "visited" : "unvisited";
+            const className = `poi-marker poi-${state}${poi === nearest ? " poi-nearest" : ""}`;
+            if (poi.className !== className) {
+                poi.className = className;
+                poi.marker.setIcon(L.divIcon({
+                    className,
+                    iconSize: [18, 18],
+                }));
+            }
+            poi.marker.setPopupContent(this.popup(poi));
+        }
+    },
+
+    popup(poi) {
+        const link = document.createElement("a");
+        link.href = `/poi/${encodeURIComponent(poi.id)}`;
+        link.textContent = poi.name;
+        const details = document.createElement("div");
+        const messages = poi.messages === 1 ? "1 message" : `${poi.messages} messages`;
+        details.textContent = `${messages} - ${Math.round(poi.distance)}m away`;
+        const popup = document.createElement("div");
+        popup.append(link, details);
+        return popup;
+    },
+};
+
+// Runs the calls `map_call` queued before this script and Leaflet were both loaded.
+(() => {
+    const run = () => {
+        const queue = window.warwickMapQueue ?? [];
+        window.warwickMapQueue = [];
+        for (const call of queue) {
+            call();
+        }
+    };
+    if (window.L) {
+        run();
+    } else {
+        // Script loads don't bubble, but they can be seen while capturing.
+        const loaded = () => {
+            if (window.L) {
+                document.removeEventListener("load", loaded, true);
+                run();
+            }
+        };
+        document.addEventListener("load", loaded, true);
+    }
+})();
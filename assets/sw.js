@@ -0,0 +1,88 @@
+// Service worker which keeps the app and the map tiles for when the campus Wi-Fi is gone.
+// Served as `/sw.js` by `server.rs`, registered by the `Offline` component.
+const APP_CACHE = "warwick-app-v1";
+const TILE_CACHE = "warwick-tiles-v1";
+// Enough for the campus at the zoom levels of the tile pack, about 20 MB.
+const MAX_TILES = 1500;
+// The bundled assets have the hash of their content in their name, like `main-dxh1a2b.css`,
+// so they never change and can come from the cache first.
+const HASHED = /-dxh[0-9a-f]+\./;
+
+// Precaches the start page and everything it links to from this server.
+self.addEventListener("install", (event) => {
+    event.waitUntil((async () => {
+        const cache = await caches.open(APP_CACHE);
+        const index = await fetch("/");
+        const html = await index.clone().text();
+        await cache.put("/", index);
+        const assets = [...html.matchAll(/(?:src|href)="(\/[^"]+)"/g)]
+            .map((m) => m[1])
+            .filter((url) => !url.startsWith("/api/"));
+        await cache.addAll([...new Set(assets)]);
+        await self.skipWaiting();
+    })());
+});
+
+self.addEventListener("activate", (event) => {
+    event.waitUntil((async () => {
+        for (const key of await caches.keys()) {
+            if (key !== APP_CACHE && key !== TILE_CACHE) {
+                await caches.delete(key);
+            }
+        }
+        await self.clients.claim();
+    })());
+});
+
+self.addEventListener("fetch", (event) => {
+    const request = event.request;
+    const url = new URL(request.url);
+    if (request.method !== "GET") {
+        return;
+    }
+    // The server functions always need the server, failed messages go to the outbox.
+    if (url.origin !== self.location.origin || url.pathname.startsWith("/api/")) {
+        return;
+    }
+    if (url.pathname.startsWith("/tiles/")) {
+        event.respondWith(cacheFirst(TILE_CACHE, request, MAX_TILES));
+    } else if (HASHED.test(url.pathname)) {
+        event.respondWith(cacheFirst(APP_CACHE, request));
+    } else {
+        event.respondWith(networkFirst(request));
+    }
+});
+
+// Keeps at most `max` responses, dropping the oldest ones.
+async function cacheFirst(name, request, max = Infinity) {
+    const cache = await caches.open(name);
+    const cached = await cache.match(request);
+    if (cached) {
+        return cached;
+    }
+    const response = await fetch(request);
+    if (response.ok || response.type === "opaque") {
+        await cache.put(request, response.clone());
+        const keys = await cache.keys();
+        for (const key of keys.slice(0, Math.max(0, keys.length - max))) {
+            await cache.delete(key);
+        }
+    }
+    return response;
+}
+
+// Pages and the files without a hash come from the server if possible, so they're up to
+// date, else from the cache, and the pages from the start page.
+async function networkFirst(request) {
+    const cache = await caches.open(APP_CACHE);
+    try {
+        const response = await fetch(request);
+        if (response.ok) {
+            await cache.put(request, response.clone());
+        }
+        return response;
+    } catch (e) {
+        const page = request.mode === "navigate" ? await cache.match("/") : undefined;
+        return (await cache.match(request)) || page || Response.error();
+    }
+}
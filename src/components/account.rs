@@ -0,0 +1,119 @@
+use dioxus::{logger::tracing, prelude::*};
+#[cfg(feature = "web")]
+use flarch::nodeids::U256;
+
+use crate::components::identity::{public_key, Recovery};
+use crate::components::storage::restore_account;
+
+/// Scanning the QR code opens the account page with the recovery code in this URL fragment.
+#[cfg(feature = "web")]
+const RESTORE_FRAGMENT: &str = "#restore=";
+
+/// Shows the recovery code of this browser, and restores an account from another one.
+#[component]
+pub fn Account() -> Element {
+    let mut show_code = use_signal(|| false);
+    let mut code_input = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    #[cfg(feature = "web")]
+    let (code, link) = {
+        use crate::components::map::web;
+        let code = Recovery {
+            user_id: web::get_storage("user_id", U256::rnd()),
+            user_key: web::user_key(),
+        }
+        .to_code();
+        let location = web_sys::window().unwrap().location();
+        let link = format!(
+            "{}{}{RESTORE_FRAGMENT}{code}",
+            location.origin().unwrap_or_default(),
+            crate::Route::Me {}
+        );
+        // Opened from a scanned QR code.
+        use_hook(|| {
+            if let Some(scanned) = location
+                .hash()
+                .ok()
+                .and_then(|h| h.strip_prefix(RESTORE_FRAGMENT).map(|c| c.to_string()))
+            {
+                code_input.set(scanned);
+            }
+        });
+        (code, link)
+    };
+    #[cfg(not(feature = "web"))]
+    let (code, link) = (String::new(), String::new());
+
+    rsx! {
+        div {
+            id: "account",
+            h2 { "Your account" }
+            button {
+                onclick: move |_| show_code.toggle(),
+                if show_code() { "Hide recovery code" } else { "Export my identity" }
+            }
+            if show_code() {
+                p { "Keep this code secret, it gives access to your account and points:" }
+                p { style: "word-break: break-all; font-family: monospace;", "{code}" }
+                div { dangerous_inner_html: "{qr_svg(&link)}" }
+                p { "Scan it with your new phone, or enter the code there." }
+            }
+            h3 { "Import identity" }
+            input {
+                value: "{code_input}",
+                oninput: move |e| code_input.set(e.value()),
+                placeholder: "Recovery code",
+                size: "50",
+            }
+            button {
+                onclick: move |_| async move {
+                    match restore(&code_input()).await {
+                        Ok(()) => error.set(None),
+                        Err(e) => error.set(Some(e)),
+                    }
+                },
+                "Restore"
+            }
+            if let Some(e) = error() {
+                p { style: "color: #e74c3c;", "{e}" }
+            }
+        }
+    }
+}
+
+/// Checks the recovery code with the server, and switches this browser to its account.
+async fn restore(code: &str) -> Result<(), String> {
+    let recovery = Recovery::from_code(code).map_err(|e| format!("Invalid recovery code: {e}"))?;
+    let account = restore_account(recovery.user_id, public_key(&recovery.user_key))
+        .await
+        .map_err(|e| format!("Couldn't restore the account: {e}"))?;
+    tracing::info!(
+        "Restoring account of {} with {} points",
+        account.name,
+        account.score.points
+    );
+    #[cfg(feature = "web")]
+    {
+        use crate::components::map::web::set_storage;
+        use crate::components::session::Session;
+        set_storage("user_id", &recovery.user_id);
+        set_storage("user_key", &recovery.user_key);
+        set_storage("user_name", &account.name);
+        set_storage("session", &None::<Session>);
+        // Start over, so every component uses the restored account.
+        let location = web_sys::window().unwrap().location();
+        let _ = location.set_hash("");
+        let _ = location.reload();
+    }
+    Ok(())
+}
+
+fn qr_svg(text: &str) -> String {
+    qrcode::QrCode::new(text.as_bytes())
+        .map(|qr| {
+            qr.render::<qrcode::render::svg::Color>()
+                .min_dimensions(200, 200)
+                .build()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::poi::DEFAULT_CHECKIN_RADIUS_M;
+
+/// Config file read at startup, if it exists.
+#[cfg(feature = "server")]
+const CONFIG_PATH: &str = "./warwick.json";
+
+/// The tiles outside of the tile pack come from here by default.
+#[cfg(feature = "server")]
+pub const OSM_TILE_SERVER: &str = "https://tile.openstreetmap.org";
+
+/// The part of the [`Config`] the client needs, sent by [`get_config`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub event_name: String,
+    pub copyright: String,
+    /// Check-in radius for POIs which don't define their own geofence.
+    pub checkin_radius_m: f64,
+    /// Maximum length of a message in characters.
+    pub max_message_len: usize,
+    /// How many of the newest messages are shown for a POI.
+    pub messages_shown: usize,
+    pub features: Features,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            event_name: "Warwick POIs".into(),
+            copyright: "(c) 2025 by Linus  Gasser for EPFL/C4DT".into(),
+            checkin_radius_m: DEFAULT_CHECKIN_RADIUS_M,
+            max_message_len: 500,
+            messages_shown: 50,
+            features: Features::default(),
+        }
+    }
+}
+
+/// Parts of the app which can be switched off.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Features {
+    pub messages: bool,
+    pub leaderboard: bool,
+    pub stats: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            messages: true,
+            leaderboard: true,
+            stats: true,
+        }
+    }
+}
+
+/// The server configuration, read from `warwick.json` or the file given in `WARWICK_CONFIG`.
+/// Every value can be overridden by its `WARWICK_*` environment variable.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Holds the POI catalog and the storage.
+    pub data_dir: String,
+    /// The storage backend: `json`, `sqlite`, or `eventlog`.
+    pub storage: String,
+    /// How long a login is valid.
+    pub session_hours: i64,
+    /// Words which can't be part of a user name, in addition to the reserved ones.
+    pub name_blocklist: Vec<String>,
+    /// Gives access to the admin page, which is disabled if it's empty.
+    pub admin_token: String,
+    /// Where the tiles outside of the tile pack are redirected to, and the tile pack is
+    /// downloaded from. The default OpenStreetMap servers don't allow bulk downloads,
+    /// so the tile pack needs another one. If it's empty, only the tile pack is shown.
+    pub tile_server: String,
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+#[cfg(feature = "server")]
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: "./data".into(),
+            storage: "json".into(),
+            session_hours: 7 * 24,
+            name_blocklist: vec![],
+            admin_token: String::new(),
+            tile_server: OSM_TILE_SERVER.into(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl Config {
+    pub fn load() -> Result<Self> {
+        use anyhow::anyhow;
+        use std::io::ErrorKind;
+
+        let explicit = std::env::var("WARWICK_CONFIG").ok();
+        let path = explicit.clone().unwrap_or(CONFIG_PATH.into());
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| anyhow!("Couldn't parse {path}: {e}"))?,
+            // Only a config file which was asked for has to exist.
+            Err(e) if e.kind() == ErrorKind::NotFound && explicit.is_none() => Config::default(),
+            Err(e) => return Err(anyhow!("Couldn't read {path}: {e}")),
+        };
+        config.override_from_env()?;
+        config.check()?;
+        Ok(config)
+    }
+
+    fn override_from_env(&mut self) -> Result<()> {
+        let s = &mut self.settings;
+        override_from_env(&mut self.data_dir, "WARWICK_DATA_DIR")?;
+        override_from_env(&mut self.storage, "WARWICK_STORAGE")?;
+        override_from_env(&mut self.session_hours, "WARWICK_SESSION_HOURS")?;
+        override_from_env(&mut self.admin_token, "WARWICK_ADMIN_TOKEN")?;
+        override_from_env(&mut self.tile_server, "WARWICK_TILE_SERVER")?;
+        if let Ok(words) = std::env::var("WARWICK_NAME_BLOCKLIST") {
+            self.name_blocklist = words
+                .split(',')
+                .map(|w| w.trim().to_string())
+                .filter(|w| !w.is_empty())
+                .collect();
+        }
+        override_from_env(&mut s.event_name, "WARWICK_EVENT_NAME")?;
+        override_from_env(&mut s.copyright, "WARWICK_COPYRIGHT")?;
+        override_from_env(&mut s.checkin_radius_m, "WARWICK_CHECKIN_RADIUS_M")?;
+        override_from_env(&mut s.max_message_len, "WARWICK_MAX_MESSAGE_LEN")?;
+        override_from_env(&mut s.messages_shown, "WARWICK_MESSAGES_SHOWN")?;
+        override_from_env(&mut s.features.messages, "WARWICK_MESSAGES")?;
+        override_from_env(&mut s.features.leaderboard, "WARWICK_LEADERBOARD")?;
+        override_from_env(&mut s.features.stats, "WARWICK_STATS")?;
+        Ok(())
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.settings.checkin_radius_m.is_nan() || self.settings.checkin_radius_m <= 0. {
+            return Err(anyhow::anyhow!("The check-in radius must be positive"));
+        }
+        if self.session_hours <= 0 {
+            return Err(anyhow::anyhow!("Sessions must last at least an hour"));
+        }
+        if self.settings.max_message_len == 0 {
+            return Err(anyhow::anyhow!(
+                "The maximum message length must be positive"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Path of `file` in the data directory.
+    pub fn path(&self, file: &str) -> String {
+        std::path::Path::new(&self.data_dir)
+            .join(file)
+            .to_string_lossy()
+            .into()
+    }
+}
+
+#[cfg(feature = "server")]
+fn override_from_env<T: std::str::FromStr>(value: &mut T, name: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(v) = std::env::var(name) {
+        *value = v
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {name}={v}: {e}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+static CONFIG: std::sync::OnceLock<Config> = std::sync::OnceLock::new();
+
+/// Loads the configuration at startup, so the server refuses to start if it's invalid.
+#[cfg(feature = "server")]
+pub fn init() -> Result<()> {
+    let config = Config::load()?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load().expect("Invalid configuration"))
+}
+
+#[server]
+pub async fn get_config() -> Result<Settings, ServerFnError> {
+    Ok(config().settings.clone())
+}
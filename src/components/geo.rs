@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+/// Mean earth radius as used by the haversine formula.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatLon {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl LatLon {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Great-circle distance in metres on a spherical earth.
+    /// Good to about 0.5%, which is plenty for the distances on a campus.
+    pub fn distance_m(&self, other: &LatLon) -> f64 {
+        let (phi1, phi2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dphi = phi2 - phi1;
+        let dlambda = (other.longitude - self.longitude).to_radians();
+        let a = (dphi / 2.).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.).sin().powi(2);
+        2. * EARTH_RADIUS_M * a.sqrt().min(1.).asin()
+    }
+
+    /// Initial bearing from `self` towards `other` in degrees, clockwise from north in [0, 360).
+    pub fn bearing_deg(&self, other: &LatLon) -> f64 {
+        let (phi1, phi2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlambda = (other.longitude - self.longitude).to_radians();
+        let y = dlambda.sin() * phi2.cos();
+        let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlambda.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.)
+    }
+
+    /// The x and y of the slippy map tile containing the point at `zoom`, see
+    /// <https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames>.
+    #[cfg(feature = "server")]
+    pub fn tile(&self, zoom: u8) -> (u32, u32) {
+        let n = f64::from(1u32 << zoom);
+        let x = (self.longitude + 180.) / 360. * n;
+        let y = (1. - self.latitude.to_radians().tan().asinh() / std::f64::consts::PI) / 2. * n;
+        (
+            x.floor().clamp(0., n - 1.) as u32,
+            y.floor().clamp(0., n - 1.) as u32,
+        )
+    }
+
+    /// Returns the point at `distance_m` metres in direction `bearing_deg` from `self`.
+    #[cfg(feature = "server")]
+    pub fn destination(&self, bearing_deg: f64, distance_m: f64) -> LatLon {
+        let delta = distance_m / EARTH_RADIUS_M;
+        let theta = bearing_deg.to_radians();
+        let phi1 = self.latitude.to_radians();
+        let lambda1 = self.longitude.to_radians();
+        let phi2 = (phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos()).asin();
+        let lambda2 = lambda1
+            + (theta.sin() * delta.sin() * phi1.cos()).atan2(delta.cos() - phi1.sin() * phi2.sin());
+        LatLon::new(
+            phi2.to_degrees(),
+            (lambda2.to_degrees() + 540.).rem_euclid(360.) - 180.,
+        )
+    }
+}
+
+/// Ray casting test whether `p` lies inside the polygon given by its corners.
+/// The polygon is closed implicitly, the last corner doesn't need to repeat the first.
+pub fn point_in_polygon(p: &LatLon, polygon: &[LatLon]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[j]);
+        if (a.latitude > p.latitude) != (b.latitude > p.latitude)
+            && p.longitude
+                < (b.longitude - a.longitude) * (p.latitude - a.latitude)
+                    / (b.latitude - a.latitude)
+                    + a.longitude
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Distance in metres from `p` to the border of the polygon, 0 if `p` is inside.
+pub fn distance_to_polygon_m(p: &LatLon, polygon: &[LatLon]) -> f64 {
+    if point_in_polygon(p, polygon) {
+        return 0.;
+    }
+    // Project the corners on a plane tangent at `p`, which is exact enough for geofences
+    // of a few hundred metres.
+    let to_xy = |q: &LatLon| {
+        (
+            (q.longitude - p.longitude).to_radians()
+                * EARTH_RADIUS_M
+                * p.latitude.to_radians().cos(),
+            (q.latitude - p.latitude).to_radians() * EARTH_RADIUS_M,
+        )
+    };
+    let corners: Vec<(f64, f64)> = polygon.iter().map(to_xy).collect();
+    (0..corners.len())
+        .map(|i| distance_to_segment(corners[i], corners[(i + 1) % corners.len()]))
+        .fold(f64::INFINITY, f64::min)
+}
+
+// Distance from the origin to the segment a-b.
+fn distance_to_segment(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0. {
+        0.
+    } else {
+        (-(a.0 * dx + a.1 * dy) / len2).clamp(0., 1.)
+    };
+    (a.0 + t * dx).hypot(a.1 + t * dy)
+}
+
+/// A position as reported by the GPS, with the radius of its confidence circle.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Fix {
+    pub position: LatLon,
+    pub accuracy_m: f64,
+}
+
+/// Compass direction like "NE" for a bearing in degrees.
+pub fn compass(bearing_deg: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((bearing_deg.rem_euclid(360.) + 22.5) / 45.) as usize % 8]
+}
+
+/// An axis-aligned box in latitude/longitude. Doesn't handle the antimeridian,
+/// which is fine as long as nobody puts a campus on it.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+#[cfg(feature = "server")]
+impl BoundingBox {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a LatLon>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bbox = Self {
+            south: first.latitude,
+            west: first.longitude,
+            north: first.latitude,
+            east: first.longitude,
+        };
+        for p in points {
+            bbox.south = bbox.south.min(p.latitude);
+            bbox.west = bbox.west.min(p.longitude);
+            bbox.north = bbox.north.max(p.latitude);
+            bbox.east = bbox.east.max(p.longitude);
+        }
+        Some(bbox)
+    }
+
+    /// Grows the box by `margin_m` metres on every side.
+    pub fn expand(&self, margin_m: f64) -> Self {
+        let sw = LatLon::new(self.south, self.west);
+        let ne = LatLon::new(self.north, self.east);
+        Self {
+            south: sw.destination(180., margin_m).latitude,
+            west: sw.destination(270., margin_m).longitude,
+            north: ne.destination(0., margin_m).latitude,
+            east: ne.destination(90., margin_m).longitude,
+        }
+    }
+
+    /// The x and y of all tiles at `zoom` which cover the box.
+    pub fn tiles(&self, zoom: u8) -> Vec<(u32, u32)> {
+        let (west, north) = LatLon::new(self.north, self.west).tile(zoom);
+        let (east, south) = LatLon::new(self.south, self.east).tile(zoom);
+        (west..=east)
+            .flat_map(|x| (north..=south).map(move |y| (x, y)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dms(deg: f64, min: f64, sec: f64) -> f64 {
+        deg.signum() * (deg.abs() + min / 60. + sec / 3600.)
+    }
+
+    // Land's End to John o' Groats, from https://www.movable-type.co.uk/scripts/latlong.html
+    fn lands_end() -> LatLon {
+        LatLon::new(dms(50., 3., 59.), -dms(5., 42., 53.))
+    }
+
+    fn john_o_groats() -> LatLon {
+        LatLon::new(dms(58., 38., 38.), -dms(3., 4., 12.))
+    }
+
+    #[test]
+    fn distance() {
+        let d = lands_end().distance_m(&john_o_groats());
+        assert!((d - 968_900.).abs() < 100., "got {d}");
+        assert_eq!(john_o_groats().distance_m(&john_o_groats()), 0.);
+
+        // One degree along a meridian.
+        let d = LatLon::new(52., -1.5).distance_m(&LatLon::new(53., -1.5));
+        assert!((d - 111_195.).abs() < 1., "got {d}");
+    }
+
+    #[test]
+    fn bearing() {
+        let b = lands_end().bearing_deg(&john_o_groats());
+        assert!((b - dms(9., 7., 11.)).abs() < 0.001, "got {b}");
+
+        let origin = LatLon::new(0., 0.);
+        for (other, expected) in [
+            (LatLon::new(1., 0.), 0.),
+            (LatLon::new(0., 1.), 90.),
+            (LatLon::new(-1., 0.), 180.),
+            (LatLon::new(0., -1.), 270.),
+        ] {
+            assert!((origin.bearing_deg(&other) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn destination() {
+        // Same reference as above.
+        let start = LatLon::new(dms(53., 19., 14.), -dms(1., 43., 47.));
+        let dest = start.destination(dms(96., 1., 18.), 124_800.);
+        assert!(
+            (dest.latitude - dms(53., 11., 18.)).abs() < 0.0003,
+            "got {dest:?}"
+        );
+        assert!(
+            (dest.longitude - dms(0., 8., 0.)).abs() < 0.0003,
+            "got {dest:?}"
+        );
+
+        // Going there and measuring back gives the same distance and bearing.
+        let there = start.destination(42., 250.);
+        assert!((start.distance_m(&there) - 250.).abs() < 1e-6);
+        assert!((start.bearing_deg(&there) - 42.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn polygon() {
+        let square = [
+            LatLon::new(52.37, -1.57),
+            LatLon::new(52.37, -1.55),
+            LatLon::new(52.39, -1.55),
+            LatLon::new(52.39, -1.57),
+        ];
+        assert!(point_in_polygon(&LatLon::new(52.38, -1.56), &square));
+        assert!(!point_in_polygon(&LatLon::new(52.40, -1.56), &square));
+        assert!(!point_in_polygon(&LatLon::new(52.38, -1.54), &square));
+        assert!(!point_in_polygon(&LatLon::new(52.38, -1.56), &square[..2]));
+
+        // An L-shape, whose notch is outside.
+        let l_shape = [
+            LatLon::new(0., 0.),
+            LatLon::new(0., 2.),
+            LatLon::new(1., 2.),
+            LatLon::new(1., 1.),
+            LatLon::new(2., 1.),
+            LatLon::new(2., 0.),
+        ];
+        assert!(point_in_polygon(&LatLon::new(0.5, 1.5), &l_shape));
+        assert!(point_in_polygon(&LatLon::new(1.5, 0.5), &l_shape));
+        assert!(!point_in_polygon(&LatLon::new(1.5, 1.5), &l_shape));
+
+        assert_eq!(
+            distance_to_polygon_m(&LatLon::new(52.38, -1.56), &square),
+            0.
+        );
+        let outside = LatLon::new(52.38, -1.55).destination(90., 100.);
+        let d = distance_to_polygon_m(&outside, &square);
+        assert!((d - 100.).abs() < 0.5, "got {d}");
+    }
+}
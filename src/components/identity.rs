@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::SigningKey;
+#[cfg(feature = "server")]
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use flarch::nodeids::U256;
+use serde::{Deserialize, Serialize};
+
+/// The public Ed25519 key for the secret key kept by the client.
+pub fn public_key(secret: &U256) -> U256 {
+    SigningKey::from_bytes(&secret.to_bytes())
+        .verifying_key()
+        .to_bytes()
+        .into()
+}
+
+/// Marks the version of the recovery code.
+const RECOVERY_PREFIX: &str = "WW1-";
+
+/// Everything needed to use an account on another device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    pub user_id: U256,
+    pub user_key: U256,
+}
+
+impl Recovery {
+    #[cfg(feature = "web")]
+    pub fn to_code(&self) -> String {
+        let bytes = [self.user_id.to_bytes(), self.user_key.to_bytes()].concat();
+        format!("{RECOVERY_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn from_code(code: &str) -> Result<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(
+            code.trim()
+                .strip_prefix(RECOVERY_PREFIX)
+                .ok_or(anyhow!("Not a recovery code"))?,
+        )?;
+        if bytes.len() != 64 {
+            return Err(anyhow!("Recovery code has the wrong length"));
+        }
+        let user_id: [u8; 32] = bytes[..32].try_into()?;
+        let user_key: [u8; 32] = bytes[32..].try_into()?;
+        Ok(Recovery {
+            user_id: user_id.into(),
+            user_key: user_key.into(),
+        })
+    }
+}
+
+/// What the author of a message signs.
+/// It contains the public ID of the sender, so a signature can't be reused by someone else.
+pub fn message_digest(sender: &U256, poi: &str, time: i64, message: &str) -> U256 {
+    U256::hash_domain_parts(
+        "message",
+        &[
+            sender.as_ref(),
+            poi.as_bytes(),
+            &time.to_be_bytes(),
+            message.as_bytes(),
+        ],
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageSignature {
+    /// The public key of the author.
+    pub key: U256,
+    /// The Ed25519 signature of the [`message_digest`] in hex.
+    pub signature: String,
+}
+
+impl MessageSignature {
+    #[cfg(any(feature = "web", test))]
+    pub fn sign(secret: &U256, digest: &U256) -> Self {
+        use ed25519_dalek::Signer;
+        let key = SigningKey::from_bytes(&secret.to_bytes());
+        MessageSignature {
+            key: key.verifying_key().to_bytes().into(),
+            signature: hex::encode(key.sign(digest.as_ref()).to_bytes()),
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub fn verify(&self, digest: &U256) -> Result<()> {
+        let key = VerifyingKey::from_bytes(&self.key.to_bytes())?;
+        let bytes: [u8; 64] = hex::decode(&self.signature)?
+            .try_into()
+            .map_err(|_| anyhow!("Signature must be 64 bytes"))?;
+        key.verify(digest.as_ref(), &Signature::from_bytes(&bytes))
+            .map_err(|_| anyhow!("Invalid signature"))
+    }
+}
+
+/// A message as exported, with everything needed to verify it without the server.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedMessage {
+    /// Public ID of the sender.
+    pub sender: U256,
+    pub poi: String,
+    pub time: i64,
+    pub message: String,
+    /// Messages from before the users had keys aren't signed.
+    pub signature: Option<MessageSignature>,
+}
+
+#[cfg(feature = "server")]
+impl SignedMessage {
+    /// Checks that the message is signed with `key`, the key registered by the sender.
+    pub fn verify(&self, key: &U256) -> Result<()> {
+        let signature = self.signature.as_ref().ok_or(anyhow!("Not signed"))?;
+        if &signature.key != key {
+            return Err(anyhow!("Not signed with the key of the sender"));
+        }
+        let digest = message_digest(&self.sender, &self.poi, self.time, &self.message);
+        signature.verify(&digest)
+    }
+}
+
+/// The key a user registered, which signs all of their messages.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SenderKey {
+    /// Public ID of the sender.
+    pub sender: U256,
+    pub key: U256,
+}
+
+/// The exported messages, with the registered key of every sender, so a message can't
+/// be replaced by one signed with another key.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MessageExport {
+    pub keys: Vec<SenderKey>,
+    pub messages: Vec<SignedMessage>,
+}
+
+/// The result of checking a [`MessageExport`].
+#[cfg(feature = "server")]
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    pub valid: usize,
+    pub unsigned: usize,
+    /// Why each of the invalid messages was rejected.
+    pub invalid: Vec<String>,
+}
+
+#[cfg(feature = "server")]
+impl MessageExport {
+    pub fn verify(&self) -> Result<Verification> {
+        let mut keys = std::collections::HashMap::new();
+        for SenderKey { sender, key } in &self.keys {
+            if keys.insert(sender, key).is_some_and(|k| k != key) {
+                return Err(anyhow!("Sender {sender} has more than one key"));
+            }
+        }
+        let mut verification = Verification::default();
+        for msg in &self.messages {
+            let result = match (&msg.signature, keys.get(&msg.sender)) {
+                (None, _) => {
+                    verification.unsigned += 1;
+                    continue;
+                }
+                (Some(_), None) => Err(anyhow!("No key for the sender")),
+                (Some(_), Some(key)) => msg.verify(key),
+            };
+            match result {
+                Ok(()) => verification.valid += 1,
+                Err(e) => verification
+                    .invalid
+                    .push(format!("{e} for message at {} on {}", msg.time, msg.poi)),
+            }
+        }
+        Ok(verification)
+    }
+}
+
+/// Verifies the signatures of exported messages, given as files with the JSON
+/// returned by [`crate::components::storage::export_messages`].
+#[cfg(feature = "server")]
+pub fn verify_exports(paths: &[String]) -> Result<()> {
+    if paths.is_empty() {
+        return Err(anyhow!("Usage: verify-messages <export.json>..."));
+    }
+    let mut invalid = 0;
+    for path in paths {
+        let export: MessageExport = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let verification = export
+            .verify()
+            .map_err(|e| anyhow!("Couldn't verify {path}: {e}"))?;
+        for reason in &verification.invalid {
+            println!("! {path}: {reason}");
+        }
+        invalid += verification.invalid.len();
+        println!(
+            "{path}: {} messages, {} valid signatures, {} unsigned",
+            export.messages.len(),
+            verification.valid,
+            verification.unsigned
+        );
+    }
+    if invalid > 0 {
+        return Err(anyhow!("{invalid} invalid signatures"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(secret: &U256, sender: U256, message: &str) -> SignedMessage {
+        let (poi, time) = ("hare".to_string(), 1_700_000_000_000);
+        let digest = message_digest(&sender, &poi, time, message);
+        SignedMessage {
+            sender,
+            poi,
+            time,
+            message: message.to_string(),
+            signature: Some(MessageSignature::sign(secret, &digest)),
+        }
+    }
+
+    #[test]
+    fn export() {
+        let (secret, sender) = (U256::rnd(), U256::rnd());
+        let mut unsigned = signed(&secret, sender, "From before the keys");
+        unsigned.signature = None;
+        let export = MessageExport {
+            keys: vec![SenderKey {
+                sender,
+                key: public_key(&secret),
+            }],
+            messages: vec![signed(&secret, sender, "Nice hare"), unsigned],
+        };
+        assert_eq!(
+            export.verify().unwrap(),
+            Verification {
+                valid: 1,
+                unsigned: 1,
+                invalid: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn forged() {
+        let (secret, sender) = (U256::rnd(), U256::rnd());
+        let keys = vec![SenderKey {
+            sender,
+            key: public_key(&secret),
+        }];
+        // Changed, and signed again with another key, which is valid on its own.
+        let forged = signed(&U256::rnd(), sender, "Ugly hare");
+        assert!(forged
+            .signature
+            .as_ref()
+            .unwrap()
+            .verify(&message_digest(
+                &sender,
+                &forged.poi,
+                forged.time,
+                &forged.message
+            ))
+            .is_ok());
+        let mut tampered = signed(&secret, sender, "Nice hare");
+        tampered.message = "Ugly hare".to_string();
+        let export = MessageExport {
+            keys: keys.clone(),
+            messages: vec![signed(&secret, sender, "Nice hare"), forged, tampered],
+        };
+        let verification = export.verify().unwrap();
+        assert_eq!((verification.valid, verification.invalid.len()), (1, 2));
+
+        // The sender needs a registered key.
+        let export = MessageExport {
+            keys: vec![],
+            messages: vec![signed(&secret, sender, "Nice hare")],
+        };
+        assert_eq!(export.verify().unwrap().invalid.len(), 1);
+
+        // And only one.
+        let mut keys = keys;
+        keys.push(SenderKey {
+            sender,
+            key: public_key(&U256::rnd()),
+        });
+        let export = MessageExport {
+            keys,
+            messages: vec![],
+        };
+        assert!(export.verify().is_err());
+    }
+}
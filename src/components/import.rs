@@ -0,0 +1,455 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use quick_xml::{events::Event, Reader};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::components::config::config;
+use crate::components::poi::{Catalog, Poi, CATALOG_FILE};
+
+/// Artworks closer than this to an existing POI with a similar name are considered duplicates.
+const DEDUP_DISTANCE_M: f64 = 25.0;
+
+/// A tagged node from one of the supported export formats.
+#[derive(Debug, Clone)]
+struct OsmNode {
+    id: String,
+    latitude: f64,
+    longitude: f64,
+    tags: HashMap<String, String>,
+}
+
+impl OsmNode {
+    fn is_artwork(&self) -> bool {
+        self.tags.get("tourism").map(|t| t.as_str()) == Some("artwork")
+    }
+
+    fn to_poi(&self) -> Option<Poi> {
+        let name = self.tags.get("name")?.trim().to_string();
+        if name.is_empty() {
+            return None;
+        }
+        Some(Poi {
+            id: slug(&name),
+            name,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            description: self.tags.get("description").cloned().unwrap_or_default(),
+            category: self
+                .tags
+                .get("artwork_type")
+                .cloned()
+                .unwrap_or("artwork".to_string()),
+            artist: self
+                .tags
+                .get("artist_name")
+                .or(self.tags.get("artist"))
+                .cloned()
+                .unwrap_or_default(),
+            // `start_date` can also be a full date like 2009-05-01.
+            year: self
+                .tags
+                .get("start_date")
+                .and_then(|d| d.get(..4))
+                .and_then(|y| y.parse().ok()),
+            image: self.image(),
+            osm: Some(self.id.clone()).filter(|id| !id.is_empty()),
+            fence: None,
+        })
+    }
+
+    fn image(&self) -> Option<String> {
+        if let Some(image) = self.tags.get("image") {
+            return Some(image.clone());
+        }
+        self.tags
+            .get("wikimedia_commons")
+            .and_then(|c| c.strip_prefix("File:"))
+            .map(|file| {
+                format!(
+                    "https://commons.wikimedia.org/wiki/Special:FilePath/{}",
+                    file.replace(' ', "_")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub duplicates: Vec<String>,
+    pub unnamed: usize,
+}
+
+/// Usage: `server import <export-file> [catalog.json] [--dry-run]`
+pub fn run(args: &[String]) -> Result<()> {
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let paths: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let input = paths.first().ok_or(anyhow!(
+        "Usage: import <export-file> [catalog.json] [--dry-run]"
+    ))?;
+    let catalog_path = paths
+        .get(1)
+        .map(|p| p.to_string())
+        .unwrap_or(config().path(CATALOG_FILE));
+    let catalog_path = catalog_path.as_str();
+
+    let mut catalog = if Path::new(catalog_path).exists() {
+        Catalog::load(catalog_path)?
+    } else {
+        Catalog::default()
+    };
+    let nodes = parse_file(input)?;
+    let report = merge(&mut catalog, &nodes);
+
+    for name in &report.added {
+        println!("+ {name}");
+    }
+    for name in &report.duplicates {
+        println!("= {name} (already in catalog)");
+    }
+    println!(
+        "{} added, {} duplicates, {} artworks without a name",
+        report.added.len(),
+        report.duplicates.len(),
+        report.unnamed
+    );
+    if !dry_run {
+        catalog.save(catalog_path)?;
+    }
+    Ok(())
+}
+
+fn parse_file(path: &str) -> Result<Vec<OsmNode>> {
+    let input = fs::read_to_string(path)?;
+    let is_xml =
+        path.ends_with(".osm") || path.ends_with(".xml") || input.trim_start().starts_with('<');
+    if is_xml {
+        return parse_osm_xml(&input);
+    }
+    let json: Value = serde_json::from_str(&input)?;
+    if json.get("type").and_then(|t| t.as_str()) == Some("FeatureCollection") {
+        parse_geojson(&json)
+    } else if json.get("elements").is_some() {
+        parse_overpass(&json)
+    } else {
+        Err(anyhow!(
+            "{path} is neither GeoJSON, Overpass JSON, nor OSM XML"
+        ))
+    }
+}
+
+fn tags_from(value: Option<&Value>) -> HashMap<String, String> {
+    value
+        .and_then(|v| v.as_object())
+        .map(|obj| {
+            obj.iter()
+                .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_geojson(json: &Value) -> Result<Vec<OsmNode>> {
+    let features = json
+        .get("features")
+        .and_then(|f| f.as_array())
+        .ok_or(anyhow!("FeatureCollection without features"))?;
+    Ok(features
+        .iter()
+        .filter_map(|feature| {
+            let geometry = feature.get("geometry")?;
+            if geometry.get("type")?.as_str()? != "Point" {
+                return None;
+            }
+            // GeoJSON coordinates are [longitude, latitude].
+            let coords = geometry.get("coordinates")?.as_array()?;
+            let properties = feature.get("properties");
+            Some(OsmNode {
+                id: feature
+                    .get("id")
+                    .or(properties.and_then(|p| p.get("@id")))
+                    .map(|id| id.as_str().map(|s| s.to_string()).unwrap_or(id.to_string()))
+                    .unwrap_or_default(),
+                latitude: coords.get(1)?.as_f64()?,
+                longitude: coords.first()?.as_f64()?,
+                tags: tags_from(properties),
+            })
+        })
+        .collect())
+}
+
+fn parse_overpass(json: &Value) -> Result<Vec<OsmNode>> {
+    let elements = json
+        .get("elements")
+        .and_then(|e| e.as_array())
+        .ok_or(anyhow!("Overpass JSON without elements"))?;
+    Ok(elements
+        .iter()
+        .filter_map(|element| {
+            // Ways and relations only have a position when queried with `out center`.
+            let pos = element.get("center").unwrap_or(element);
+            Some(OsmNode {
+                id: format!(
+                    "{}/{}",
+                    element.get("type")?.as_str()?,
+                    element.get("id")?.as_u64()?
+                ),
+                latitude: pos.get("lat")?.as_f64()?,
+                longitude: pos.get("lon")?.as_f64()?,
+                tags: tags_from(element.get("tags")),
+            })
+        })
+        .collect())
+}
+
+fn parse_osm_xml(input: &str) -> Result<Vec<OsmNode>> {
+    fn node_from(e: &quick_xml::events::BytesStart) -> Result<OsmNode> {
+        let mut node = OsmNode {
+            id: String::new(),
+            latitude: 0.,
+            longitude: 0.,
+            tags: HashMap::new(),
+        };
+        for attr in e.attributes() {
+            let attr = attr?;
+            let value = attr.unescape_value()?;
+            match attr.key.as_ref() {
+                b"id" => node.id = format!("node/{value}"),
+                b"lat" => node.latitude = value.parse()?,
+                b"lon" => node.longitude = value.parse()?,
+                _ => {}
+            }
+        }
+        Ok(node)
+    }
+
+    let mut reader = Reader::from_str(input);
+    let mut nodes = vec![];
+    let mut current: Option<OsmNode> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"node" => current = Some(node_from(&e)?),
+            Event::Empty(e) if e.name().as_ref() == b"node" => nodes.push(node_from(&e)?),
+            Event::Empty(e) if e.name().as_ref() == b"tag" => {
+                if let Some(node) = current.as_mut() {
+                    let (mut k, mut v) = (String::new(), String::new());
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        match attr.key.as_ref() {
+                            b"k" => k = attr.unescape_value()?.to_string(),
+                            b"v" => v = attr.unescape_value()?.to_string(),
+                            _ => {}
+                        }
+                    }
+                    node.tags.insert(k, v);
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"node" => nodes.extend(current.take()),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(nodes)
+}
+
+/// Adds all named artworks from `nodes` which are not yet in the catalog.
+fn merge(catalog: &mut Catalog, nodes: &[OsmNode]) -> ImportReport {
+    let mut report = ImportReport::default();
+    for node in nodes.iter().filter(|n| n.is_artwork()) {
+        let Some(mut poi) = node.to_poi() else {
+            report.unnamed += 1;
+            continue;
+        };
+        if catalog.pois.iter().any(|p| is_duplicate(p, &poi)) {
+            report.duplicates.push(poi.name);
+            continue;
+        }
+        // Artworks with the same name get a counter, as not every export has node IDs.
+        let base = poi.id.clone();
+        let mut counter = 2;
+        while catalog.get(&poi.id).is_some() {
+            poi.id = format!("{base}-{counter}");
+            counter += 1;
+        }
+        report.added.push(poi.name.clone());
+        catalog.pois.push(poi);
+    }
+    report
+}
+
+/// Distinct artworks can be closer than [`DEDUP_DISTANCE_M`], so only close artworks
+/// with a similar name, or the same OpenStreetMap node, are duplicates.
+fn is_duplicate(a: &Poi, b: &Poi) -> bool {
+    if a.osm.is_some() && a.osm == b.osm {
+        return true;
+    }
+    let (name_a, name_b) = (slug(&a.name), slug(&b.name));
+    let similar = name_a.contains(&name_b) || name_b.contains(&name_a);
+    similar && a.position().distance_m(&b.position()) < DEDUP_DISTANCE_M
+}
+
+/// Lowercase ASCII slug, used for IDs and to compare names.
+/// Names without any ASCII letters or digits get a slug from the hash of the name.
+fn slug(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .replace('\'', "")
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        return format!("poi-{}", hex::encode(&Sha256::digest(name.as_bytes())[..4]));
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use flarch::nodeids::U256;
+
+    use super::*;
+
+    fn artwork(id: &str, name: &str, latitude: f64, longitude: f64) -> OsmNode {
+        OsmNode {
+            id: id.to_string(),
+            latitude,
+            longitude,
+            tags: HashMap::from([
+                ("tourism".to_string(), "artwork".to_string()),
+                ("name".to_string(), name.to_string()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn geojson() -> Result<()> {
+        let json = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "id": "node/1",
+                    "geometry": {"type": "Point", "coordinates": [6.5668, 46.5191]},
+                    "properties": {"tourism": "artwork", "name": "Hare", "start_date": "2009-05-01"}
+                },
+                {
+                    "type": "Feature",
+                    "geometry": {"type": "Point", "coordinates": [6.5670, 46.5192]},
+                    "properties": {"@id": "node/2", "tourism": "artwork"}
+                },
+                {
+                    "type": "Feature",
+                    "geometry": {"type": "LineString", "coordinates": [[6.5, 46.5], [6.6, 46.6]]},
+                    "properties": {"tourism": "artwork", "name": "Path"}
+                }
+            ]
+        });
+        let nodes = parse_geojson(&json)?;
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].id, "node/1");
+        assert_eq!((nodes[0].latitude, nodes[0].longitude), (46.5191, 6.5668));
+        assert_eq!(nodes[1].id, "node/2");
+
+        let poi = nodes[0].to_poi().expect("named artwork");
+        assert_eq!(poi.id, "hare");
+        assert_eq!(poi.year, Some(2009));
+        assert_eq!(poi.osm.as_deref(), Some("node/1"));
+        assert!(nodes[1].to_poi().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn overpass() -> Result<()> {
+        let json = serde_json::json!({
+            "elements": [
+                {"type": "node", "id": 1, "lat": 46.5191, "lon": 6.5668,
+                    "tags": {"tourism": "artwork", "name": "Hare"}},
+                {"type": "way", "id": 2, "center": {"lat": 46.5192, "lon": 6.5670},
+                    "tags": {"tourism": "artwork", "name": "Ripple Effect"}},
+                {"type": "relation", "id": 3, "tags": {"tourism": "artwork"}}
+            ]
+        });
+        let nodes = parse_overpass(&json)?;
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].id, "node/1");
+        assert_eq!(nodes[1].id, "way/2");
+        assert_eq!((nodes[1].latitude, nodes[1].longitude), (46.5192, 6.5670));
+        assert_eq!(
+            nodes[1].tags.get("name").map(|n| n.as_str()),
+            Some("Ripple Effect")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Let's not be stupid!"), "lets-not-be-stupid");
+        let cyrillic = slug("Скульптура");
+        assert!(
+            cyrillic.starts_with("poi-") && cyrillic.len() == 12,
+            "got {cyrillic}"
+        );
+        assert_eq!(cyrillic, slug("Скульптура"));
+        assert_ne!(cyrillic, slug("Памятник"));
+    }
+
+    #[test]
+    fn duplicates() {
+        let poi = |id, name, longitude| {
+            artwork(id, name, 46.5191, longitude)
+                .to_poi()
+                .expect("named artwork")
+        };
+        let hare = poi("node/1", "Hare", 6.5668);
+        // About 8m away.
+        assert!(is_duplicate(&hare, &poi("", "The Hare", 6.5669)));
+        assert!(!is_duplicate(&hare, &poi("", "Forest", 6.5669)));
+        // About 80m away.
+        assert!(!is_duplicate(&hare, &poi("", "Hare", 6.5678)));
+        // The same node, even after it moved.
+        assert!(is_duplicate(&hare, &poi("node/1", "Hare", 6.5678)));
+    }
+
+    #[test]
+    fn merge_ids() -> Result<()> {
+        let mut catalog = Catalog::default();
+        let nodes = [
+            artwork("", "Bench", 46.5191, 6.5668),
+            artwork("", "Bench", 46.5201, 6.5668),
+            artwork("", "Bench", 46.5211, 6.5668),
+            artwork("", "Скульптура", 46.5221, 6.5668),
+            artwork("", "Скульптура", 46.5231, 6.5668),
+            // A duplicate of the first bench.
+            artwork("", "Bench", 46.5191, 6.5669),
+            artwork("node/9", "", 46.5241, 6.5668),
+        ];
+        let report = merge(&mut catalog, &nodes);
+        assert_eq!(report.added.len(), 5);
+        assert_eq!(report.duplicates, vec!["Bench".to_string()]);
+        assert_eq!(report.unnamed, 1);
+        let ids: Vec<&str> = catalog.pois.iter().map(|p| p.id.as_str()).collect();
+        let sculpture = slug("Скульптура");
+        assert_eq!(
+            ids,
+            vec![
+                "bench",
+                "bench-2",
+                "bench-3",
+                sculpture.as_str(),
+                &format!("{sculpture}-2")
+            ]
+        );
+
+        // The catalog the importer writes can be loaded by the server.
+        let path = std::env::temp_dir().join(format!("warwick-import-{}.json", U256::rnd()));
+        let path = path.to_str().unwrap();
+        catalog.save(path)?;
+        let loaded = Catalog::load(path);
+        fs::remove_file(path)?;
+        assert_eq!(loaded?.pois.len(), 5);
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use dioxus::prelude::*;
+use flarch::nodeids::U256;
+
+#[cfg(feature = "web")]
+use crate::components::storage::public_id;
+use crate::components::storage::{get_leaderboard, RankedUser};
+use crate::Route;
+
+#[component]
+pub fn Leaderboard() -> Element {
+    let mut page = use_signal(|| 0usize);
+    #[cfg(feature = "web")]
+    let me = Some(public_id(&crate::components::map::web::get_storage(
+        "user_id",
+        U256::rnd(),
+    )));
+    #[cfg(not(feature = "web"))]
+    let me: Option<U256> = None;
+    let board = use_server_future(move || get_leaderboard(page(), me))?;
+
+    rsx! {
+        div {
+            id: "leaderboard",
+            h2 { "Leaderboard" }
+            if let Some(Ok(board)) = board() {
+                table {
+                    style: "margin: auto; border-collapse: collapse;",
+                    tr {
+                        th { "#" }
+                        th { "Name" }
+                        th { "Points" }
+                        th { "POIs" }
+                    }
+                    for entry in board.entries.iter() {
+                        Row { entry: entry.clone(), highlight: Some(entry.user.id_public) == me }
+                    }
+                    if let Some(mine) = board.me.filter(|m| !board.entries.contains(m)) {
+                        tr { td { colspan: "4", "..." } }
+                        Row { entry: mine, highlight: true }
+                    }
+                }
+                if board.pages > 1 {
+                    div {
+                        button {
+                            disabled: board.page == 0,
+                            onclick: move |_| page -= 1,
+                            "<"
+                        }
+                        " Page {board.page + 1} of {board.pages} "
+                        button {
+                            disabled: board.page + 1 >= board.pages,
+                            onclick: move |_| page += 1,
+                            ">"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn Row(entry: RankedUser, highlight: bool) -> Element {
+    let style = if highlight {
+        "font-weight: bold; background-color: #2c3e50;"
+    } else {
+        ""
+    };
+    rsx! {
+        tr {
+            style: "{style}",
+            td { "{entry.rank}" }
+            td {
+                Link {
+                    to: Route::UserPage { id: format!("{:x}", entry.user.id_public) },
+                    "{entry.user.name}"
+                }
+                if entry.user.completed.is_some() {
+                    " ★"
+                }
+            }
+            td { "{entry.user.points}" }
+            td { "{entry.user.collected}" }
+        }
+    }
+}
@@ -1,130 +1,36 @@
-use std::{collections::HashMap, fmt::Error};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use anyhow::Result;
-use dioxus::{fullstack::once_cell::sync::OnceCell, logger::tracing, prelude::*};
-use dioxus_leaflet::{Map, MapMarker, MapPosition, MarkerIcon};
+use dioxus::{logger::tracing, prelude::*};
 use flarch::{nodeids::U256, tasks::now};
-use flmacro::VersionedSerde;
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
-struct _POI {
-    latitude: f64,
-    longitude: f64,
-    name: &'static str,
-}
-
-const _POIS: [_POI; 11] = [
-    _POI {
-        latitude: 52.378933,
-        longitude: -1.562204,
-        name: "Let's not be stupid",
-    },
-    _POI {
-        latitude: 52.379486,
-        longitude: -1.562931,
-        name: "Days of Judgement - Cat I",
-    },
-    _POI {
-        latitude: 52.379046,
-        longitude: -1.565627,
-        name: "Song - Version V",
-    },
-    _POI {
-        latitude: 52.375521,
-        longitude: -1.565444,
-        name: "Hare",
-    },
-    _POI {
-        latitude: 52.379095,
-        longitude: -1.561604,
-        name: "Ripple Effect",
-    },
-    _POI {
-        latitude: 52.380092,
-        longitude: -1.559804,
-        name: "Forest 2011 - 2 Planet",
-    },
-    _POI {
-        latitude: 52.380189,
-        longitude: -1.560257,
-        name: "Butterworth Bench",
-    },
-    _POI {
-        latitude: 52.380320,
-        longitude: -1.560126,
-        name: "Forest 2011 - 3 Planet",
-    },
-    _POI {
-        latitude: 52.380328,
-        longitude: -1.559839,
-        name: "Forest Planet - 3 2009",
-    },
-    _POI {
-        latitude: 52.380010,
-        longitude: -1.560788,
-        name: "White Koan",
-    },
-    _POI {
-        latitude: 52.377715,
-        longitude: -1.567944,
-        name: "The good, the bad",
-    },
-];
-
-static QRCODE: Asset = asset!("/assets/qrcode.png");
-
-#[component]
-pub fn MapPOI() -> Element {
-    rsx! {
-        div {
-            style: "text-align: center;",
-            h1 { "Warwick POIs - Collect 'em all!" }
-            MapPOIWeb{}
-            p { "(c) 2025 by Linus  Gasser for EPFL/C4DT" }
-            a { href: "https://github.com/c4dt/rse_warwick", "Github Repo" }
-            br {}
-            br {}
-            img { src: QRCODE, style: "width: 50%;" }
-        }
-    }
-}
-
-use super::*;
-use crate::components::storage::{add_message, get_messages, get_stats};
+use crate::components::config::Settings;
+use crate::components::geo::{Fix, LatLon};
+use crate::components::identity::{message_digest, MessageSignature};
+use crate::components::offline::{post_check_in, post_message};
+use crate::components::poi::{get_pois, poi_name, presence, Poi, Presence};
+use crate::components::profile::my_id_public;
+use crate::components::session::Session;
+use crate::components::storage::{
+    get_messages, get_poi_summaries, get_score, get_stats, public_id, PoiSummary,
+};
+use crate::Route;
 use chrono::prelude::DateTime;
 use chrono::{Local, Utc};
-use dioxus_leaflet::MapOptions;
-use flarch::tasks::{spawn_local, wait_ms};
-
-use crate::components::storage::store_user;
 
 #[component]
-pub fn MapPOIWeb() -> Element {
-    let (mut latitude, mut longitude) = (0f64, 0f64);
-    #[cfg(feature = "web")]
-    {
-        use dioxus_sdk::geolocation::{
-            core::Geocoordinates, init_geolocator, use_geolocation, PowerMode,
-        };
-        let geolocator = init_geolocator(PowerMode::High);
-        if geolocator.read().is_err() {
-            return rsx! {};
-        }
-        let latest_coords_caller = use_geolocation();
-        tracing::info!("Going to fetch coordinates");
-        let latest_coords = match latest_coords_caller() {
-            Ok(v) => v,
-            Err(e) => {
-                tracing::info!("Not initialized yet");
-                return rsx! {};
-            }
-        };
-        (latitude, longitude) = (latest_coords.latitude, latest_coords.longitude);
-    }
-    rsx!(
-        div {
-            if latitude == 0f64 && longitude == 0f64 {
+pub fn MapPOIWeb(settings: Settings) -> Element {
+    let pois = use_server_future(get_pois)?;
+    // Shared by the list and the map, so both update after a check-in.
+    let check_ins = use_signal(|| 0usize);
+    let fix = match use_fix() {
+        Some(Ok(fix)) => fix,
+        Some(Err(e)) => {
+            return rsx! {
                 h2 { style: "
                         color: #e74c3c;
                         font-weight: bold;
@@ -132,17 +38,76 @@ pub fn MapPOIWeb() -> Element {
                         animation: gentle-pulse 3s ease-in-out infinite;
                     ",
                     "Please enable location usage for the browser!" }
-            } else {
-                List{longitude: longitude, latitude: latitude}
-                LocationTracker {longitude: longitude, latitude: latitude}
+                p { "{e}" }
             }
         }
+        None => return rsx! { p { "Waiting for the position..." } },
+    };
+    let (latitude, longitude, accuracy) = (
+        fix.position.latitude,
+        fix.position.longitude,
+        fix.accuracy_m,
+    );
+    let pois = match pois() {
+        Some(Ok(pois)) => pois,
+        Some(Err(e)) => return rsx! { p { "Couldn't load the POIs: {e}" } },
+        None => return rsx! {},
+    };
+    rsx!(
+        div {
+            List{longitude: longitude, latitude: latitude, accuracy: accuracy, pois: pois.clone(), settings: settings, check_ins: check_ins}
+            LocationTracker {longitude: longitude, latitude: latitude, accuracy: accuracy, pois: pois, check_ins: check_ins}
+        }
     )
 }
 
+/// Sends every new position of the browser with its accuracy, or why there is none, as a
+/// `Result`. Only one watch runs at a time, so remounting a page doesn't add another one.
+const WATCH_POSITION: &str = r#"
+    if (window.warwickWatch !== undefined) {
+        navigator.geolocation.clearWatch(window.warwickWatch);
+    }
+    if (!navigator.geolocation) {
+        dioxus.send({ Err: "This browser doesn't share its location" });
+    }
+    window.warwickWatch = navigator.geolocation?.watchPosition(
+        (p) => dioxus.send({ Ok: {
+            position: { latitude: p.coords.latitude, longitude: p.coords.longitude },
+            accuracy_m: p.coords.accuracy,
+        } }),
+        (e) => dioxus.send({ Err: e.code === e.PERMISSION_DENIED
+            ? "The location is blocked for this site"
+            : `Couldn't get the position: ${e.message}` }),
+        { enableHighAccuracy: true },
+    );
+    await new Promise(() => {});
+"#;
+
+/// The latest GPS fix of the browser, or why the browser has none, like a blocked
+/// permission. `None` while waiting for the first one.
+pub fn use_fix() -> Option<Result<Fix, String>> {
+    let mut fix = use_signal(|| None::<Result<Fix, String>>);
+    use_future(move || async move {
+        let mut watch = document::eval(WATCH_POSITION);
+        loop {
+            match watch.recv::<Result<Fix, String>>().await {
+                Ok(f) => fix.set(Some(f)),
+                Err(e) => {
+                    tracing::warn!("Stopped watching the position: {e:?}");
+                    break;
+                }
+            }
+        }
+    });
+    fix()
+}
+
 #[cfg(feature = "web")]
-mod web {
+pub mod web {
     use super::*;
+    use crate::components::identity::public_key;
+    use crate::components::storage::{login, renew_session};
+    use serde::de::DeserializeOwned;
 
     pub fn get_storage<T: DeserializeOwned + Serialize + std::fmt::Debug>(
         key: &str,
@@ -160,66 +125,207 @@ mod web {
 
     pub fn set_storage<T: Serialize + std::fmt::Debug>(key: &str, value: &T) {
         let local_storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
-        local_storage.set_item(key, &serde_json::to_string(value).unwrap());
+        let _ = local_storage.set_item(key, &serde_json::to_string(value).unwrap());
     }
-}
 
-#[component]
-fn List(longitude: f64, latitude: f64) -> Element {
-    let mut dists: Vec<(usize, f64)> = _POIS
-        .iter()
-        .enumerate()
-        .map(|(i, poi)| {
-            (
-                i,
-                ((poi.latitude - latitude).powf(2.0) + (poi.longitude - longitude).powf(2.0))
-                    .sqrt(),
-            )
-        })
-        .collect();
-    dists.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-    let closest = dists.first().unwrap();
-    let (name, distance) = (
-        _POIS[closest.0].name,
-        (closest.1 * 100000.).floor() as usize,
-    );
-    let mut user_id = U256::rnd();
-    let mut user_name = format!("Unknown");
+    /// The secret key the user signs messages with, created on the first use.
+    pub fn user_key() -> U256 {
+        get_storage("user_key", U256::rnd())
+    }
+
+    /// Sessions are renewed a bit before they expire, so a request never fails with an
+    /// expired session.
+    const SESSION_RENEWAL_MS: i64 = 60 * 60 * 1000;
+
+    /// Returns the stored session, renews it with the server if it's about to expire, or
+    /// logs in again if there is no valid one for the current user.
+    pub async fn session() -> Result<Session, ServerFnError> {
+        let user_id = get_storage("user_id", U256::rnd());
+        if let Some(session) = get_storage::<Option<Session>>("session", None) {
+            if session.id_public == public_id(&user_id) {
+                if session.expires > now() + SESSION_RENEWAL_MS {
+                    return Ok(session);
+                }
+                if session.expires > now() {
+                    match renew_session(session).await {
+                        Ok(session) => {
+                            set_storage("session", &Some(session.clone()));
+                            return Ok(session);
+                        }
+                        Err(e) => tracing::warn!("Couldn't renew the session: {e}"),
+                    }
+                }
+            }
+        }
+        let logged_in = login(
+            user_id,
+            get_storage("user_name", "Unknown".to_string()),
+            public_key(&user_key()),
+        )
+        .await?;
+        // The server picks another name if the stored one isn't allowed.
+        set_storage("user_name", &logged_in.name);
+        set_storage("session", &Some(logged_in.session.clone()));
+        Ok(logged_in.session)
+    }
+}
 
+/// Signs a message with the key of this browser, only available in the web build.
+pub fn sign_message(
+    sender: &U256,
+    poi: &str,
+    time: i64,
+    message: &str,
+) -> Result<MessageSignature, ServerFnError> {
+    let digest = message_digest(sender, poi, time, message);
     #[cfg(feature = "web")]
+    return Ok(MessageSignature::sign(&web::user_key(), &digest));
+    #[cfg(not(feature = "web"))]
     {
-        user_id = web::get_storage("user_id", U256::rnd());
-        user_name = web::get_storage("user_name", names::Generator::default().next().unwrap());
-        spawn_local(async move {
-            store_user(
-                user_id,
-                web::get_storage("user_name", "Unknown".to_string()),
-            )
-            .await;
-        });
+        let _ = digest;
+        Err(ServerFnError::new(
+            "Signing is only available in the browser",
+        ))
     }
+}
 
-    rsx! {
-        if distance < 20 {
-            p { "{user_name}, you're at POI {name}!" }
-            Messages{poi: closest.0}
-        } else {
-            Stats{}
-            p { "{user_name}, your closest POI is {name} at {distance}m - get closer than 20m" }
+/// The session of this browser, only available in the web build.
+pub async fn session() -> Result<Session, ServerFnError> {
+    #[cfg(feature = "web")]
+    return web::session().await;
+    #[cfg(not(feature = "web"))]
+    Err(ServerFnError::new(
+        "Sessions are only available in the browser",
+    ))
+}
+
+/// Checks in at the POI the user is at, once per POI in a row. The POI is only recorded
+/// after the check-in succeeded, so failed ones are retried with the next fix.
+/// Returns the POI of the last check-in, which may be queued until the user is online.
+pub fn use_check_in(at: Option<(&Poi, Fix)>, mut check_ins: Signal<usize>) -> Option<String> {
+    let mut checked_in = use_signal(|| None::<String>);
+    // Not a signal, as it only keeps a second check-in from starting while one runs.
+    let checking_in = use_hook(|| Rc::new(Cell::new(false)));
+    if let Some((poi, fix)) = at {
+        if checked_in.peek().as_ref() != Some(&poi.id) && !checking_in.get() {
+            checking_in.set(true);
+            let poi_id = poi.id.clone();
+            let checking_in = checking_in.clone();
+            spawn(async move {
+                match post_check_in(poi_id.clone(), fix).await {
+                    Ok(score) => {
+                        checked_in.set(Some(poi_id));
+                        // Queued check-ins don't change the visits yet.
+                        if score.is_some() {
+                            *check_ins.write() += 1;
+                        }
+                    }
+                    Err(e) => tracing::warn!("Couldn't check in: {e}"),
+                }
+                checking_in.set(false);
+            });
+        }
+    }
+    checked_in()
+}
+
+#[component]
+fn List(
+    longitude: f64,
+    latitude: f64,
+    accuracy: f64,
+    pois: Vec<Poi>,
+    settings: Settings,
+    mut check_ins: Signal<usize>,
+) -> Element {
+    let fix = Fix {
+        position: LatLon::new(latitude, longitude),
+        accuracy_m: accuracy,
+    };
+    #[cfg(feature = "web")]
+    let (user_id, user_name) = (
+        web::get_storage("user_id", U256::rnd()),
+        web::get_storage("user_name", names::Generator::default().next().unwrap()),
+    );
+    #[cfg(not(feature = "web"))]
+    let (user_id, user_name) = (U256::rnd(), "Unknown".to_string());
+    // Logs in once, which also registers new users.
+    use_future(|| async {
+        if let Err(e) = session().await {
+            tracing::warn!("Couldn't log in: {e}");
         }
+    });
+
+    let presence = presence(&pois, &fix);
+    let at = match &presence {
+        Presence::At(poi) => Some((poi, fix)),
+        _ => None,
+    };
+    use_check_in(at, check_ins);
+
+    let list = match presence {
+        Presence::NoPois => rsx! { p { "There are no POIs yet" } },
+        Presence::PoorFix { accuracy_m } => rsx! {
+            if settings.features.stats {
+                Stats{pois: pois.clone()}
+            }
+            p { "{user_name}, waiting for a better GPS fix (currently ±{accuracy_m.round()}m)..." }
+        },
+        Presence::At(poi) => rsx! {
+            p {
+                "{user_name}, you're at POI "
+                Link { to: Route::PoiPage { id: poi.id.clone() }, "{poi.name}" }
+                "!"
+            }
+            if settings.features.messages {
+                Messages{poi: poi, settings: settings.clone(), can_post: true}
+            }
+        },
+        Presence::Away {
+            closest,
+            distance_m,
+        } => rsx! {
+            if settings.features.stats {
+                Stats{pois: pois.clone()}
+            }
+            p { "{user_name}, your closest POI is {closest.name} at {distance_m.floor()}m - get closer to check in" }
+        },
+    };
+    rsx! {
+        Points{id_public: public_id(&user_id), check_ins: check_ins, total: pois.len()}
+        {list}
     }
 }
 
 #[component]
-fn Stats() -> Element {
-    let stats = use_server_future(move || get_stats())?;
+fn Points(id_public: U256, check_ins: Signal<usize>, total: usize) -> Element {
+    let score = use_server_future(move || {
+        // Reload the score after every check-in.
+        check_ins();
+        get_score(id_public)
+    })?;
+    rsx!(
+        if let Some(Ok(s)) = score() {
+            p {
+                "You have {s.points} points and collected {s.collected.len()} of {total} POIs"
+                if s.completed.is_some() {
+                    " - you caught 'em all!"
+                }
+            }
+        }
+    )
+}
+
+#[component]
+fn Stats(pois: Vec<Poi>) -> Element {
+    let stats = use_server_future(get_stats)?;
     rsx!(
         if let Some(Ok(s)) = stats() {
             if let Some(last) = s.last{
                 div {
                     "Stats: {s.total_users} users - {s.total_messages} messages"
                     br{}
-                    "last message at __{_POIS[last.1].name}__ from '{last.0.sender}':"
+                    "last message at __{poi_name(&pois, &last.1)}__ from '{last.0.sender}':"
                     br{}
                     "''{last.0.message}'' at {unix_to_str(last.0.time)}"
                 }
@@ -230,44 +336,64 @@ fn Stats() -> Element {
     )
 }
 
-fn unix_to_str(unix: i64) -> String {
+pub fn unix_to_str(unix: i64) -> String {
     let datetime = DateTime::<Utc>::from_timestamp_millis(unix).unwrap();
     datetime.with_timezone(&Local).to_rfc2822()
 }
 
+/// The messages of a POI. Only users who are at the POI can post.
 #[component]
-fn Messages(poi: usize) -> Element {
-    let mut messages = use_server_future(move || get_messages(poi))?;
-    let mut input_text = use_signal(|| String::new());
-    let mut user_id = U256::rnd();
-    #[cfg(feature = "web")]
-    let mut user_id = web::get_storage("user_id", U256::rnd());
+pub fn Messages(poi: Poi, settings: Settings, can_post: bool) -> Element {
+    let poi_id = poi.id.clone();
+    let mut messages = use_server_future(move || get_messages(poi_id.clone()))?;
+    let mut input_text = use_signal(String::new);
+    let mut queued = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
 
     rsx! {
-        textarea {
-            value: "{input_text}",
-            oninput: move |e| input_text.set(e.value()),
-            placeholder: "Enter multi-line text",
-            rows: "4",
-            cols: "50"
-        }
+        if can_post {
+            textarea {
+                value: "{input_text}",
+                oninput: move |e| input_text.set(e.value()),
+                placeholder: "Enter multi-line text",
+                maxlength: "{settings.max_message_len}",
+                rows: "4",
+                cols: "50"
+            }
 
-        br{}
+            br{}
 
-        button {
-            onclick: move |_| {
-                async move {
-                    add_message(user_id, poi, input_text()).await;
-                    messages.restart();
-                }
-            },
-            { "Submit" }
+            button {
+                onclick: move |_| {
+                    let poi_id = poi.id.clone();
+                    async move {
+                        let (time, text) = (now(), input_text());
+                        match post_message(poi_id, text, time).await {
+                            Ok(was_queued) => {
+                                queued.set(was_queued);
+                                error.set(None);
+                            }
+                            Err(e) => error.set(Some(format!("Couldn't add the message: {e}"))),
+                        }
+                        messages.restart();
+                    }
+                },
+                { "Submit" }
+            }
+            if queued() {
+                p { i { "You're offline, the message will be sent later" } }
+            }
+            if let Some(e) = error() {
+                p { style: "color: #e74c3c;", "{e}" }
+            }
+        } else {
+            p { i { "Go to {poi.name} to leave a message" } }
         }
 
         if let Some(Ok(msgs)) = messages(){
-            if msgs.len() > 0{
-                p{"Here are the messages for {_POIS[poi].name}"}
-                for msg in msgs.iter().rev() {
+            if !msgs.is_empty() {
+                p{"Here are the messages for {poi.name}"}
+                for msg in msgs.iter().rev().take(settings.messages_shown) {
                     p{"-- '{msg.sender}' wrote ''{msg.message}'' at {unix_to_str(msg.time)}"}
                 }
             } else {
@@ -277,53 +403,261 @@ fn Messages(poi: usize) -> Element {
     }
 }
 
+static MAP_JS: Asset = asset!("/assets/map.js");
+static LEAFLET_JS: Asset = asset!("/assets/leaflet/leaflet.js");
+static LEAFLET_CSS: Asset = asset!("/assets/leaflet/leaflet.css");
+static USER_POSITION_ICON: Asset = asset!("/assets/icons/user-position.svg");
+static USER_HEADING_ICON: Asset = asset!("/assets/icons/user-heading.svg");
+/// The user has to move this far before the heading between two fixes is shown.
+const HEADING_MIN_DISTANCE_M: f64 = 5.;
+
+/// Calls the glue in `assets/map.js`. Until it and Leaflet are loaded, the calls are
+/// queued, and `map.js` runs them once they are.
+fn map_call(js: String) {
+    document::eval(&format!(
+        "const call = () => {{ {js} }};
+        if (window.L && window.warwickMap) {{
+            call();
+        }} else {{
+            (window.warwickMapQueue ??= []).push(call);
+        }}"
+    ));
+}
+
+/// A POI as drawn by `assets/map.js`.
+#[derive(Serialize, Debug)]
+struct MapPoi {
+    id: String,
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    messages: usize,
+    visited: bool,
+    new_messages: bool,
+}
+
+impl MapPoi {
+    fn new(poi: &Poi, summary: Option<&PoiSummary>) -> Self {
+        MapPoi {
+            id: poi.id.clone(),
+            name: poi.name.clone(),
+            latitude: poi.latitude,
+            longitude: poi.longitude,
+            messages: summary.map(|s| s.messages).unwrap_or_default(),
+            visited: summary.is_some_and(|s| s.visited.is_some()),
+            new_messages: summary.is_some_and(|s| s.new_messages),
+        }
+    }
+}
+
+/// Loads the visits of the user for the map, again after every check-in.
 #[component]
-fn LocationTracker(latitude: f64, longitude: f64) -> Element {
-    let mut update = use_signal(|| 0);
-    use_resource(move || async move {
-        loop {
-            wait_ms(10).await;
-            *update.write() += 1;
+fn LocationTracker(
+    latitude: ReadOnlySignal<f64>,
+    longitude: ReadOnlySignal<f64>,
+    accuracy: ReadOnlySignal<f64>,
+    pois: Vec<Poi>,
+    check_ins: Signal<usize>,
+) -> Element {
+    let id_public = my_id_public();
+    let summaries = use_resource(move || async move {
+        check_ins();
+        get_poi_summaries(id_public).await
+    });
+    let summaries = match &*summaries.read() {
+        Some(Ok(summaries)) => summaries.clone(),
+        Some(Err(e)) => {
+            tracing::warn!("Couldn't load the POI summaries: {e}");
+            vec![]
         }
+        None => vec![],
+    };
+    rsx! {
+        PoiMap { latitude, longitude, accuracy, pois, summaries }
+    }
+}
+
+/// The map with the POIs and the user. It's only created once, new positions only
+/// move the marker of the user, and new visits or messages only restyle the POIs.
+#[component]
+fn PoiMap(
+    latitude: ReadOnlySignal<f64>,
+    longitude: ReadOnlySignal<f64>,
+    accuracy: ReadOnlySignal<f64>,
+    pois: ReadOnlySignal<Vec<Poi>>,
+    summaries: ReadOnlySignal<Vec<PoiSummary>>,
+) -> Element {
+    use_effect(move || {
+        let summaries = summaries.read();
+        let markers: Vec<MapPoi> = pois
+            .read()
+            .iter()
+            .map(|poi| MapPoi::new(poi, summaries.iter().find(|s| s.poi == poi.id)))
+            .collect();
+        let markers = serde_json::to_string(&markers).unwrap_or("[]".into());
+        map_call(format!("warwickMap.setPois({markers});"));
     });
 
-    let mut path_markers: Vec<MapMarker> = _POIS
-        .iter()
-        .enumerate()
-        .map(|(i, p)| MapMarker {
-            lat: p.latitude,
-            lng: p.longitude,
-            title: p.name.into(),
-            description: None,
-            icon: None,
-            popup_options: None,
-            custom_data: None,
-        })
-        .collect();
-    path_markers.push(MapMarker {
-        lat: latitude,
-        lng: longitude,
-        title: "Position".into(),
-        description: None,
-        icon: Some(MarkerIcon {
-            icon_url: "https://img.icons8.com/define-location".into(),
-            icon_size: Some((32, 32)),
-            icon_anchor: None,
-            popup_anchor: None,
-            shadow_url: None,
-            shadow_size: None,
-        }),
-        popup_options: None,
-        custom_data: None,
+    // The position where the heading was last computed, and the heading.
+    let last = use_hook(|| Rc::new(RefCell::new((None::<LatLon>, None::<f64>))));
+    use_effect(move || {
+        let position = LatLon::new(latitude(), longitude());
+        let mut last = last.borrow_mut();
+        match last.0 {
+            Some(previous) if previous.distance_m(&position) < HEADING_MIN_DISTANCE_M => {}
+            Some(previous) => *last = (Some(position), Some(previous.bearing_deg(&position))),
+            None => last.0 = Some(position),
+        }
+        let heading = last.1.map(|h| h.round().to_string());
+        map_call(format!(
+            "warwickMap.moveUser({}, {}, {}, {});",
+            position.latitude,
+            position.longitude,
+            accuracy(),
+            heading.unwrap_or("null".into())
+        ));
     });
 
     rsx! {
-        if update() % 500 > 0 {
-            Map {
-                initial_position: MapPosition::new(latitude, longitude, 32.),
-                markers: path_markers,
-                height: "500px",
+        document::Link { rel: "stylesheet", href: LEAFLET_CSS }
+        document::Script { src: LEAFLET_JS }
+        document::Script { src: MAP_JS }
+        div {
+            id: "map",
+            style: "height: 500px;",
+            onmounted: move |_| {
+                map_call(format!(
+                    "warwickMap.create('map', {}, {}, {}, {{ position: '{USER_POSITION_ICON}', heading: '{USER_HEADING_ICON}' }});",
+                    latitude.peek(),
+                    longitude.peek(),
+                    accuracy.peek()
+                ));
+            },
+        }
+        div {
+            class: "map-legend",
+            span { class: "poi-marker poi-unvisited" }
+            "to find "
+            span { class: "poi-marker poi-visited" }
+            "visited "
+            span { class: "poi-marker poi-nearest" }
+            "nearest "
+            span { class: "poi-marker poi-new-messages" }
+            "new messages"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use dioxus::dioxus_core::{DynamicNode, NoOpMutations};
+    use dioxus::document::{Document, Eval, NoOpDocument};
+
+    use super::*;
+
+    thread_local! {
+        static EVALS: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+        static SIGNALS: Cell<Option<WalkSignals>> = const { Cell::new(None) };
+    }
+
+    /// The latitude and the visits of [`Walk`].
+    type WalkSignals = (Signal<f64>, Signal<Vec<PoiSummary>>);
+
+    /// Keeps the JavaScript the components run.
+    struct Recorder;
+
+    impl Document for Recorder {
+        fn eval(&self, js: String) -> Eval {
+            EVALS.with(|evals| evals.borrow_mut().push(js.clone()));
+            NoOpDocument.eval(js)
+        }
+    }
+
+    const FIXES: usize = 50;
+    const START: f64 = 52.38;
+    const STEP: f64 = 0.0001;
+
+    fn map_calls(method: &str) -> Vec<String> {
+        EVALS.with(|evals| {
+            evals
+                .borrow()
+                .iter()
+                .filter(|js| js.contains(&format!("warwickMap.{method}(")))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// A user at a POI, whose fixes and visits are set by the test.
+    #[allow(non_snake_case)]
+    fn Walk() -> Element {
+        use_context_provider(|| Rc::new(Recorder) as Rc<dyn Document>);
+        let latitude = use_signal(|| START);
+        let summaries = use_signal(Vec::<PoiSummary>::new);
+        use_hook(|| SIGNALS.with(|s| s.set(Some((latitude, summaries)))));
+        let hare: Poi = serde_json::from_str(
+            r#"{"id": "hare", "name": "Hare", "latitude": 52.385, "longitude": -1.56}"#,
+        )
+        .unwrap();
+        rsx! {
+            PoiMap {
+                latitude: latitude(),
+                longitude: -1.56,
+                accuracy: 10.,
+                pois: vec![hare],
+                summaries: summaries(),
             }
         }
     }
+
+    /// How often the [`PoiMap`] of [`Walk`] ran.
+    fn map_renders(dom: &VirtualDom) -> usize {
+        let walk = dom.get_scope(ScopeId::APP).unwrap().root_node();
+        let DynamicNode::Component(map) = &walk.dynamic_nodes[0] else {
+            panic!("Walk renders a map");
+        };
+        let map = map.mounted_scope_id(0, walk, dom).unwrap();
+        dom.in_runtime(|| map.generation()).unwrap()
+    }
+
+    /// Renders the changes, and then runs the effects.
+    fn update(dom: &mut VirtualDom) {
+        dom.render_immediate(&mut NoOpMutations);
+        dom.render_immediate(&mut NoOpMutations);
+    }
+
+    #[test]
+    fn map_renders_once() {
+        let mut dom = VirtualDom::new(Walk);
+        dom.rebuild_in_place();
+        update(&mut dom);
+        let (mut latitude, mut summaries) = SIGNALS.with(|s| s.get()).unwrap();
+        for _ in 0..FIXES {
+            dom.in_runtime(|| latitude += STEP);
+            update(&mut dom);
+        }
+        dom.in_runtime(|| {
+            summaries.set(vec![PoiSummary {
+                poi: "hare".into(),
+                messages: 1,
+                visited: Some(now()),
+                new_messages: false,
+            }])
+        });
+        update(&mut dom);
+
+        assert_eq!(map_renders(&dom), 1);
+        let moves = map_calls("moveUser");
+        assert_eq!(moves.len(), FIXES + 1);
+        let latitude = (0..FIXES).fold(START, |l, _| l + STEP);
+        let last = moves.last().unwrap();
+        assert!(last.contains(&format!("warwickMap.moveUser({latitude}, -1.56, 10, ")));
+        // Walking north.
+        assert!(last.contains(", 0);"));
+        let pois = map_calls("setPois");
+        assert_eq!(pois.len(), 2);
+        assert!(pois[1].contains(r#""visited":true"#));
+    }
 }
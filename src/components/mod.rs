@@ -1,5 +1,19 @@
 // mod echo;
 // pub use echo::Echo;
 
+pub mod account;
+pub mod config;
+pub mod geo;
+pub mod identity;
+#[cfg(feature = "server")]
+pub mod import;
+pub mod leaderboard;
 pub mod map;
-pub mod storage;
\ No newline at end of file
+pub mod offline;
+pub mod pages;
+pub mod poi;
+pub mod profile;
+pub mod session;
+pub mod storage;
+#[cfg(feature = "server")]
+pub mod tiles;
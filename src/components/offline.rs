@@ -0,0 +1,205 @@
+use dioxus::{logger::tracing, prelude::*};
+use flarch::{nodeids::U256, tasks::now};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::components::geo::Fix;
+use crate::components::identity::MessageSignature;
+use crate::components::map::{session, sign_message};
+#[cfg(feature = "web")]
+use crate::components::storage::public_id;
+use crate::components::storage::{add_message, check_in, Score, OFFLINE_MAX_AGE_MS};
+
+/// A message which couldn't be sent yet, kept in the local storage.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedMessage {
+    pub poi: String,
+    pub message: String,
+    pub time: i64,
+    pub signature: MessageSignature,
+}
+
+/// A check-in which couldn't be sent yet. The server needs it before the messages
+/// written at the POI.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedCheckIn {
+    pub poi: String,
+    pub fix: Fix,
+    pub time: i64,
+}
+
+const OUTBOX: &str = "outbox";
+const CHECK_IN_OUTBOX: &str = "outbox_check_ins";
+
+/// Registers the service worker, once the page is loaded.
+const REGISTER_SERVICE_WORKER: &str = r#"
+    if ("serviceWorker" in navigator) {
+        navigator.serviceWorker.register("/sw.js");
+    }
+"#;
+
+/// Returns once the browser is back online, or after a minute to try again anyway.
+const WAIT_ONLINE: &str = r#"
+    await new Promise((r) => {
+        window.addEventListener("online", r, { once: true });
+        setTimeout(r, 60000);
+    });
+    dioxus.send(true);
+"#;
+
+/// Whether the server couldn't be reached, as opposed to refusing the request.
+fn unreachable(e: &ServerFnError) -> bool {
+    matches!(e, ServerFnError::Request(_))
+}
+
+/// Signs and sends the message. If the server can't be reached, the message is queued
+/// and `Ok(true)` returned.
+pub async fn post_message(poi: String, message: String, time: i64) -> Result<bool, ServerFnError> {
+    #[cfg(feature = "web")]
+    let sender = public_id(&crate::components::map::web::get_storage(
+        "user_id",
+        U256::rnd(),
+    ));
+    #[cfg(not(feature = "web"))]
+    let sender = U256::rnd();
+    let signature = sign_message(&sender, &poi, time, &message)?;
+    let sent = match session().await {
+        Ok(session) => {
+            add_message(
+                session,
+                poi.clone(),
+                message.clone(),
+                time,
+                signature.clone(),
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    match sent {
+        Ok(()) => Ok(false),
+        Err(e) if unreachable(&e) => {
+            tracing::info!("Server unreachable, queuing the message");
+            let mut outbox: Vec<QueuedMessage> = queued(OUTBOX);
+            outbox.push(QueuedMessage {
+                poi,
+                message,
+                time,
+                signature,
+            });
+            set_queued(OUTBOX, &outbox);
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks in at the POI. If the server can't be reached, the check-in is queued with
+/// its fix and time, and `Ok(None)` returned.
+pub async fn post_check_in(poi: String, fix: Fix) -> Result<Option<Score>, ServerFnError> {
+    let time = now();
+    let checked_in = match session().await {
+        Ok(session) => check_in(session, poi.clone(), fix, time).await,
+        Err(e) => Err(e),
+    };
+    match checked_in {
+        Ok(score) => Ok(Some(score)),
+        Err(e) if unreachable(&e) => {
+            tracing::info!("Server unreachable, queuing the check-in");
+            let mut outbox: Vec<QueuedCheckIn> = queued(CHECK_IN_OUTBOX);
+            outbox.push(QueuedCheckIn { poi, fix, time });
+            set_queued(CHECK_IN_OUTBOX, &outbox);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn queued<T: DeserializeOwned + Serialize + std::fmt::Debug>(key: &str) -> Vec<T> {
+    #[cfg(feature = "web")]
+    return crate::components::map::web::get_storage(key, vec![]);
+    #[cfg(not(feature = "web"))]
+    {
+        let _ = key;
+        vec![]
+    }
+}
+
+fn set_queued<T: Serialize + std::fmt::Debug>(key: &str, outbox: &[T]) {
+    #[cfg(feature = "web")]
+    crate::components::map::web::set_storage(key, &outbox);
+    #[cfg(not(feature = "web"))]
+    let _ = (key, outbox);
+}
+
+/// Sends the queued check-ins, then the queued messages, and returns how many are
+/// still waiting.
+/// Check-ins the server refuses are dropped. Refused messages are kept until they're
+/// too old, as they may only have been refused because their check-in is still waiting.
+pub async fn flush() -> usize {
+    let check_ins: Vec<QueuedCheckIn> = queued(CHECK_IN_OUTBOX);
+    let messages: Vec<QueuedMessage> = queued(OUTBOX);
+    if check_ins.is_empty() && messages.is_empty() {
+        return 0;
+    }
+    let session = match session().await {
+        Ok(session) => session,
+        Err(e) => {
+            tracing::info!("Can't send the queued check-ins and messages yet: {e}");
+            return check_ins.len() + messages.len();
+        }
+    };
+
+    let mut waiting_check_ins = vec![];
+    for queued in check_ins {
+        let sent = check_in(session.clone(), queued.poi.clone(), queued.fix, queued.time).await;
+        match sent {
+            Ok(_) => tracing::info!("Sent the queued check-in at {}", queued.poi),
+            Err(e) if unreachable(&e) => waiting_check_ins.push(queued),
+            Err(e) => tracing::warn!("Dropping the queued check-in at {}: {e}", queued.poi),
+        }
+    }
+    set_queued(CHECK_IN_OUTBOX, &waiting_check_ins);
+
+    let mut waiting = vec![];
+    for msg in messages {
+        let sent = add_message(
+            session.clone(),
+            msg.poi.clone(),
+            msg.message.clone(),
+            msg.time,
+            msg.signature.clone(),
+        )
+        .await;
+        match sent {
+            Ok(()) => tracing::info!("Sent the queued message at {}", msg.poi),
+            Err(e) if unreachable(&e) => waiting.push(msg),
+            Err(e) if now() - msg.time < OFFLINE_MAX_AGE_MS => {
+                tracing::info!("Keeping the queued message at {}: {e}", msg.poi);
+                waiting.push(msg);
+            }
+            Err(e) => tracing::warn!("Dropping the queued message at {}: {e}", msg.poi),
+        }
+    }
+    set_queued(OUTBOX, &waiting);
+    waiting_check_ins.len() + waiting.len()
+}
+
+/// Registers the service worker, and sends the queued messages whenever the browser
+/// is back online.
+#[component]
+pub fn Offline() -> Element {
+    let mut waiting = use_signal(|| 0usize);
+    use_future(move || async move {
+        document::eval(REGISTER_SERVICE_WORKER);
+        loop {
+            waiting.set(flush().await);
+            let _ = document::eval(WAIT_ONLINE).recv::<bool>().await;
+        }
+    });
+
+    rsx! {
+        if waiting() > 0 {
+            p { i { "{waiting} check-ins and messages will be sent once you're online" } }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use dioxus::prelude::*;
+use flarch::nodeids::U256;
+
+use crate::components::account::Account;
+use crate::components::config::{get_config, Settings};
+use crate::components::geo::compass;
+use crate::components::leaderboard::Leaderboard;
+use crate::components::map::{unix_to_str, use_check_in, use_fix, MapPOIWeb, Messages};
+use crate::components::offline::Offline;
+use crate::components::poi::{get_pois, presence, Presence};
+use crate::components::profile::{my_id_public, Profile};
+use crate::components::storage::get_moderation;
+use crate::Route;
+
+static QRCODE: Asset = asset!("/assets/qrcode.png");
+
+/// Loads the settings once for all pages.
+#[component]
+pub fn Layout() -> Element {
+    let settings = use_server_future(get_config)?;
+    match settings() {
+        Some(Ok(settings)) => rsx! { Frame { settings } },
+        Some(Err(e)) => rsx! { p { "Couldn't load the configuration: {e}" } },
+        None => rsx! {},
+    }
+}
+
+/// Header and navigation around every page. The pages get the settings from the context.
+#[component]
+fn Frame(settings: Settings) -> Element {
+    use_context_provider(|| settings.clone());
+    rsx! {
+        div {
+            style: "text-align: center;",
+            h1 { "{settings.event_name} - Collect 'em all!" }
+            nav {
+                Link { to: Route::Home {}, "Map" }
+                " | "
+                if settings.features.leaderboard {
+                    Link { to: Route::LeaderboardPage {}, "Leaderboard" }
+                    " | "
+                }
+                Link { to: Route::Me {}, "Me" }
+                " | "
+                Link { to: Route::About {}, "About" }
+            }
+            Offline {}
+            Outlet::<Route> {}
+            p { "{settings.copyright}" }
+        }
+    }
+}
+
+#[component]
+pub fn Home() -> Element {
+    rsx! {
+        MapPOIWeb { settings: use_context::<Settings>() }
+    }
+}
+
+/// Everything about a POI, which can be browsed from anywhere.
+#[component]
+pub fn PoiPage(id: String) -> Element {
+    let settings = use_context::<Settings>();
+    let pois = use_server_future(get_pois)?;
+    let fix = use_fix().and_then(Result::ok);
+    let check_ins = use_signal(|| 0usize);
+    let pois = pois().and_then(Result::ok);
+    let poi = pois
+        .as_ref()
+        .and_then(|pois| pois.iter().find(|p| p.id == id).cloned());
+    // Posting needs a recorded visit, so users who open the page at the POI check in
+    // like on the home page.
+    let at = poi
+        .as_ref()
+        .zip(fix)
+        .filter(|(poi, fix)| matches!(presence(std::slice::from_ref(*poi), fix), Presence::At(_)));
+    let present = at.is_some();
+    let checked_in = use_check_in(at, check_ins);
+    if pois.is_none() {
+        return rsx! {};
+    }
+    let Some(poi) = poi else {
+        return rsx! { p { "There is no POI {id}" } };
+    };
+    let can_post = present && checked_in.as_ref() == Some(&poi.id);
+    let direction = fix.map(|fix| {
+        let distance = fix.position.distance_m(&poi.position()).round();
+        let bearing = fix.position.bearing_deg(&poi.position());
+        format!("{distance}m {} of you", compass(bearing))
+    });
+    let made_by = match (poi.artist.is_empty(), poi.year) {
+        (false, Some(year)) => format!("{} ({year})", poi.artist),
+        (false, None) => poi.artist.clone(),
+        (true, Some(year)) => format!("{year}"),
+        (true, None) => String::new(),
+    };
+
+    rsx! {
+        h2 { "{poi.name}" }
+        if !poi.category.is_empty() {
+            p { i { "{poi.category}" } }
+        }
+        if !made_by.is_empty() {
+            p { "{made_by}" }
+        }
+        if let Some(image) = &poi.image {
+            img { src: "{image}", alt: "{poi.name}", style: "max-width: 80%;" }
+        }
+        p { "{poi.description}" }
+        if let Some(direction) = direction {
+            p { "{direction}" }
+        }
+        if let Some(url) = poi.osm_url() {
+            a { href: "{url}", "Show on OpenStreetMap" }
+        }
+        if settings.features.messages {
+            Messages { poi, settings, can_post }
+        }
+    }
+}
+
+#[component]
+pub fn LeaderboardPage() -> Element {
+    if !use_context::<Settings>().features.leaderboard {
+        return rsx! { p { "The leaderboard is disabled" } };
+    }
+    rsx! { Leaderboard {} }
+}
+
+#[component]
+pub fn Me() -> Element {
+    rsx! {
+        Profile { id_public: my_id_public() }
+        Account {}
+    }
+}
+
+#[component]
+pub fn UserPage(id: String) -> Element {
+    // Parsing panics on anything but 64 hex digits.
+    let valid = id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit());
+    match valid.then(|| id.parse::<U256>()) {
+        Some(Ok(id_public)) => rsx! { Profile { key: "{id}", id_public } },
+        _ => rsx! { p { "Invalid user {id}" } },
+    }
+}
+
+#[component]
+pub fn About() -> Element {
+    rsx! {
+        p { "Walk around the campus, find the sculptures, and check in when you're close to them." }
+        p { "Every new sculpture and the first message at it gives points, collecting all of them gives a bonus." }
+        a { href: "https://github.com/c4dt/rse_warwick", "Github Repo" }
+        br {}
+        br {}
+        img { src: QRCODE, style: "width: 50%;" }
+    }
+}
+
+/// Shows the name history of the users to the organizers.
+#[component]
+pub fn Admin() -> Element {
+    let mut token_input = use_signal(String::new);
+    let mut token = use_signal(|| None::<String>);
+    let users = use_resource(move || async move {
+        match token() {
+            Some(token) => Some(get_moderation(token).await),
+            None => None,
+        }
+    });
+
+    rsx! {
+        h2 { "Administration" }
+        input {
+            r#type: "password",
+            value: "{token_input}",
+            oninput: move |e| token_input.set(e.value()),
+            placeholder: "Admin token",
+        }
+        button { onclick: move |_| token.set(Some(token_input())), "Log in" }
+        match users() {
+            Some(Some(Ok(users))) => rsx! {
+                table {
+                    style: "margin: auto; border-collapse: collapse;",
+                    tr {
+                        th { "Name" }
+                        th { "Points" }
+                        th { "Previous names" }
+                    }
+                    for moderated in users {
+                        tr {
+                            td {
+                                Link {
+                                    to: Route::UserPage { id: format!("{:x}", moderated.user.id_public) },
+                                    "{moderated.user.name}"
+                                }
+                            }
+                            td { "{moderated.user.points}" }
+                            td {
+                                for change in moderated.previous_names.iter() {
+                                    "{change.name} (until {unix_to_str(change.until)}) "
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            Some(Some(Err(e))) => rsx! { p { "{e}" } },
+            _ => rsx! {},
+        }
+    }
+}
+
+#[component]
+pub fn NotFound(route: Vec<String>) -> Element {
+    let path = route.join("/");
+    rsx! {
+        p { "There is no page /{path}" }
+        Link { to: Route::Home {}, "Back to the map" }
+    }
+}
@@ -0,0 +1,329 @@
+#[cfg(feature = "server")]
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::geo::{distance_to_polygon_m, Fix, LatLon};
+
+/// The catalog file in the data directory.
+#[cfg(feature = "server")]
+pub const CATALOG_FILE: &str = "pois.json";
+
+/// Check-in radius for POIs which don't define their own geofence, unless configured
+/// otherwise.
+pub const DEFAULT_CHECKIN_RADIUS_M: f64 = 20.;
+
+/// Fixes less accurate than this can't tell whether a user is at a POI.
+pub const MAX_ACCURACY_M: f64 = 50.;
+
+/// IDs of the POIs in the order of the old hardcoded array.
+/// Messages stored before the catalog existed refer to the POI by this index.
+#[cfg(feature = "server")]
+pub const LEGACY_IDS: [&str; 11] = [
+    "lets-not-be-stupid",
+    "days-of-judgement-cat-i",
+    "song-version-v",
+    "hare",
+    "ripple-effect",
+    "forest-2011-2-planet",
+    "butterworth-bench",
+    "forest-2011-3-planet",
+    "forest-planet-3-2009",
+    "white-koan",
+    "the-good-the-bad",
+];
+
+#[cfg(feature = "server")]
+pub fn legacy_id(index: usize) -> String {
+    LEGACY_IDS
+        .get(index)
+        .map(|id| id.to_string())
+        .unwrap_or(format!("legacy-{index}"))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Poi {
+    pub id: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    /// URL of a photo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The OpenStreetMap element, like `node/1234`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm: Option<String>,
+    /// Where users can check in, defaults to the configured radius around the POI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fence: Option<Geofence>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Geofence {
+    Circle { radius_m: f64 },
+    Polygon { points: Vec<LatLon> },
+}
+
+impl Poi {
+    pub fn position(&self) -> LatLon {
+        LatLon::new(self.latitude, self.longitude)
+    }
+
+    pub fn osm_url(&self) -> Option<String> {
+        self.osm
+            .as_ref()
+            .map(|osm| format!("https://www.openstreetmap.org/{osm}"))
+    }
+
+    pub fn fence(&self) -> Geofence {
+        self.fence.clone().unwrap_or(Geofence::Circle {
+            radius_m: DEFAULT_CHECKIN_RADIUS_M,
+        })
+    }
+
+    /// Distance from `p` to the geofence, 0 if `p` is inside.
+    pub fn fence_distance_m(&self, p: &LatLon) -> f64 {
+        match self.fence() {
+            Geofence::Circle { radius_m } => (self.position().distance_m(p) - radius_m).max(0.),
+            Geofence::Polygon { points } => distance_to_polygon_m(p, &points),
+        }
+    }
+
+    /// Whether the accuracy circle of the fix touches the geofence.
+    #[cfg(feature = "server")]
+    pub fn contains(&self, fix: &Fix) -> bool {
+        self.fence_distance_m(&fix.position) <= fix.accuracy_m
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Presence {
+    NoPois,
+    /// The fix is too inaccurate to decide.
+    PoorFix {
+        accuracy_m: f64,
+    },
+    At(Poi),
+    Away {
+        closest: Poi,
+        distance_m: f64,
+    },
+}
+
+/// Where the user is with regard to the POIs, given a GPS fix.
+pub fn presence(pois: &[Poi], fix: &Fix) -> Presence {
+    if fix.accuracy_m > MAX_ACCURACY_M {
+        return Presence::PoorFix {
+            accuracy_m: fix.accuracy_m,
+        };
+    }
+    // Use the distance to the fence, so large POIs win even if another center is closer.
+    let closest = pois
+        .iter()
+        .map(|poi| (poi, poi.fence_distance_m(&fix.position)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    match closest {
+        None => Presence::NoPois,
+        Some((poi, distance_m)) if distance_m <= fix.accuracy_m => Presence::At(poi.clone()),
+        Some((poi, distance_m)) => Presence::Away {
+            closest: poi.clone(),
+            distance_m,
+        },
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Catalog {
+    pub pois: Vec<Poi>,
+}
+
+#[cfg(feature = "server")]
+impl Catalog {
+    pub fn load(path: &str) -> Result<Self> {
+        let catalog: Catalog = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let mut ids: Vec<&str> = catalog.pois.iter().map(|p| p.id.as_str()).collect();
+        ids.sort();
+        if let Some(dup) = ids.windows(2).find(|w| w[0] == w[1]) {
+            return Err(anyhow::anyhow!("Duplicate POI id {} in {path}", dup[0]));
+        }
+        Ok(catalog)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Poi> {
+        self.pois.iter().find(|p| p.id == id)
+    }
+}
+
+/// Loads the catalog from the data directory, and gives the POIs without their own
+/// geofence the configured check-in radius.
+#[cfg(feature = "server")]
+pub fn load_catalog() -> Result<Catalog> {
+    let config = crate::components::config::config();
+    let mut catalog = Catalog::load(&config.path(CATALOG_FILE))?;
+    for poi in catalog.pois.iter_mut() {
+        poi.fence.get_or_insert(Geofence::Circle {
+            radius_m: config.settings.checkin_radius_m,
+        });
+    }
+    Ok(catalog)
+}
+
+pub fn poi_name(pois: &[Poi], id: &str) -> String {
+    pois.iter()
+        .find(|p| p.id == id)
+        .map(|p| p.name.clone())
+        .unwrap_or(id.to_string())
+}
+
+#[server]
+pub async fn get_pois() -> Result<Vec<Poi>, ServerFnError> {
+    Ok(load_catalog().map_err(ServerFnError::new)?.pois)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: LatLon = LatLon {
+        latitude: 46.5191,
+        longitude: 6.5668,
+    };
+
+    fn poi(id: &str, position: LatLon, fence: Option<Geofence>) -> Poi {
+        Poi {
+            id: id.to_string(),
+            name: id.to_string(),
+            latitude: position.latitude,
+            longitude: position.longitude,
+            description: String::new(),
+            category: String::new(),
+            artist: String::new(),
+            year: None,
+            image: None,
+            osm: None,
+            fence,
+        }
+    }
+
+    /// A fix `distance_m` metres east of the center.
+    fn fix(distance_m: f64, accuracy_m: f64) -> Fix {
+        Fix {
+            position: CENTER.destination(90., distance_m),
+            accuracy_m,
+        }
+    }
+
+    /// A square with sides of 100m around the center.
+    fn square() -> Geofence {
+        Geofence::Polygon {
+            points: [45., 135., 225., 315.]
+                .iter()
+                .map(|bearing| CENTER.destination(*bearing, 50. * 2f64.sqrt()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn circle() {
+        let hare = poi("hare", CENTER, Some(Geofence::Circle { radius_m: 30. }));
+        assert!(hare.contains(&fix(0., 0.)));
+        assert!(hare.contains(&fix(29., 0.)));
+        assert!(!hare.contains(&fix(31., 0.)));
+        let d = hare.fence_distance_m(&fix(50., 0.).position);
+        assert!((d - 20.).abs() < 0.01, "got {d}");
+
+        // Without a fence, the default radius is used.
+        let bench = poi("bench", CENTER, None);
+        assert!(bench.contains(&fix(DEFAULT_CHECKIN_RADIUS_M - 1., 0.)));
+        assert!(!bench.contains(&fix(DEFAULT_CHECKIN_RADIUS_M + 1., 0.)));
+    }
+
+    #[test]
+    fn polygon() {
+        let forest = poi("forest", CENTER, Some(square()));
+        assert!(forest.contains(&fix(0., 0.)));
+        assert!(forest.contains(&fix(45., 0.)));
+        assert_eq!(forest.fence_distance_m(&fix(45., 0.).position), 0.);
+        assert!(!forest.contains(&fix(55., 0.)));
+        let d = forest.fence_distance_m(&fix(60., 0.).position);
+        assert!((d - 10.).abs() < 0.1, "got {d}");
+    }
+
+    #[test]
+    fn accuracy_enlarges_the_fence() {
+        let hare = poi("hare", CENTER, Some(Geofence::Circle { radius_m: 20. }));
+        assert!(!hare.contains(&fix(30., 9.)));
+        assert!(hare.contains(&fix(30., 11.)));
+
+        let forest = poi("forest", CENTER, Some(square()));
+        assert!(!forest.contains(&fix(60., 9.)));
+        assert!(forest.contains(&fix(60., 11.)));
+    }
+
+    #[test]
+    fn presence_of_fixes() {
+        let hare = poi("hare", CENTER, Some(Geofence::Circle { radius_m: 20. }));
+        let bench = poi(
+            "bench",
+            CENTER.destination(0., 200.),
+            Some(Geofence::Circle { radius_m: 20. }),
+        );
+        let pois = [hare.clone(), bench.clone()];
+
+        assert_eq!(presence(&[], &fix(0., 5.)), Presence::NoPois);
+        assert_eq!(presence(&pois, &fix(0., 5.)), Presence::At(hare.clone()));
+        assert_eq!(
+            presence(&pois, &fix(0., MAX_ACCURACY_M + 1.)),
+            Presence::PoorFix {
+                accuracy_m: MAX_ACCURACY_M + 1.
+            }
+        );
+        // The least accurate fix which still counts enlarges the fence the most.
+        assert_eq!(
+            presence(&pois, &fix(60., MAX_ACCURACY_M)),
+            Presence::At(hare.clone())
+        );
+        match presence(&pois, &fix(60., 5.)) {
+            Presence::Away {
+                closest,
+                distance_m,
+            } => {
+                assert_eq!(closest, hare);
+                assert!((distance_m - 40.).abs() < 0.01, "got {distance_m}");
+            }
+            p => panic!("Expected to be away, got {p:?}"),
+        }
+
+        // A large POI wins over one whose center is closer.
+        let forest = poi("forest", CENTER.destination(270., 40.), Some(square()));
+        let near = poi(
+            "near",
+            CENTER.destination(90., 25.),
+            Some(Geofence::Circle { radius_m: 10. }),
+        );
+        assert_eq!(
+            presence(&[near, forest.clone()], &fix(5., 5.)),
+            Presence::At(forest)
+        );
+    }
+}
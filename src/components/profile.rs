@@ -0,0 +1,183 @@
+use dioxus::{logger::tracing, prelude::*};
+use flarch::nodeids::U256;
+
+use crate::components::map::unix_to_str;
+use crate::components::poi::{get_pois, poi_name};
+#[cfg(feature = "web")]
+use crate::components::storage::public_id;
+use crate::components::storage::{get_profile, get_user, rename_user};
+
+pub const NAME_MIN_LEN: usize = 3;
+pub const NAME_MAX_LEN: usize = 24;
+/// How often a user can change the name within 24 hours.
+#[cfg(feature = "server")]
+pub const RENAMES_PER_DAY: usize = 3;
+/// Only the organizers may look like them, checked in addition to the configured blocklist.
+const RESERVED_NAMES: [&str; 4] = ["admin", "moderator", "c4dt", "epfl"];
+
+/// Checks a new user name and returns it without the surrounding spaces.
+/// The client only knows the reserved names, the server also checks the `blocklist`.
+pub fn validate_name(name: &str, blocklist: &[String]) -> Result<String, String> {
+    let name = name.trim();
+    let len = name.chars().count();
+    if !(NAME_MIN_LEN..=NAME_MAX_LEN).contains(&len) {
+        return Err(format!(
+            "Names must have between {NAME_MIN_LEN} and {NAME_MAX_LEN} characters"
+        ));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_alphanumeric() && !" -_.'".contains(*c))
+    {
+        return Err(format!("'{c}' is not allowed in names"));
+    }
+    let lower = name.to_lowercase();
+    let blocked = RESERVED_NAMES
+        .iter()
+        .map(|w| w.to_string())
+        .chain(blocklist.iter().map(|w| w.to_lowercase()))
+        .any(|w| lower.contains(&w));
+    if blocked {
+        return Err("This name is not allowed".into());
+    }
+    Ok(name.to_string())
+}
+
+/// The public ID of the user of this browser.
+pub fn my_id_public() -> U256 {
+    #[cfg(feature = "web")]
+    return public_id(&crate::components::map::web::get_storage(
+        "user_id",
+        U256::rnd(),
+    ));
+    #[cfg(not(feature = "web"))]
+    U256::rnd()
+}
+
+/// Name, points, collected POIs, and messages of a user.
+#[component]
+pub fn Profile(id_public: U256) -> Element {
+    let mut profile = use_server_future(move || get_profile(id_public))?;
+    let pois = use_server_future(get_pois)?;
+    let own = my_id_public() == id_public;
+    let (profile_data, pois) = match (profile(), pois()) {
+        (Some(Ok(Some(profile))), Some(Ok(pois))) => (profile, pois),
+        (Some(Ok(None)), _) => return rsx! { p { "There is no such user" } },
+        (Some(Err(e)), _) | (_, Some(Err(e))) => {
+            return rsx! { p { "Couldn't load the profile: {e}" } }
+        }
+        _ => return rsx! {},
+    };
+    let collected = profile_data.collected.len();
+    let total = profile_data.total_pois;
+    let percent = collected * 100 / total.max(1);
+    let missing = pois
+        .iter()
+        .filter(|p| !profile_data.collected.iter().any(|(id, _)| id == &p.id))
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    rsx! {
+        div {
+            id: "profile",
+            h2 { "{profile_data.user.name}" }
+            if own {
+                ProfileEditor { onrename: move |_| profile.restart() }
+            }
+            p { "Rank {profile_data.rank} with {profile_data.user.points} points" }
+            div {
+                style: "margin: auto; width: 80%; background-color: #34495e; border-radius: 5px;",
+                div {
+                    style: "width: {percent}%; min-width: 3em; background-color: #27ae60; border-radius: 5px;",
+                    "{collected}/{total}"
+                }
+            }
+            if let Some(completed) = profile_data.user.completed {
+                p { "Caught 'em all on {unix_to_str(completed)}" }
+            }
+            h3 { "Collected POIs" }
+            if collected == 0 {
+                p { "None yet" }
+            }
+            for (id, time) in profile_data.collected.iter() {
+                p { "{poi_name(&pois, id)} - {unix_to_str(*time)}" }
+            }
+            if !missing.is_empty() && collected > 0 {
+                p { "Still to find: {missing}" }
+            }
+            h3 { "Messages" }
+            if profile_data.messages.is_empty() {
+                p { "No messages yet" }
+            }
+            for msg in profile_data.messages.iter() {
+                p { "''{msg.message}'' at __{poi_name(&pois, &msg.poi)}__ on {unix_to_str(msg.time)}" }
+            }
+        }
+    }
+}
+
+/// Shows the name of the user and lets them change it.
+#[component]
+pub fn ProfileEditor(onrename: Option<EventHandler<String>>) -> Element {
+    let id_public = my_id_public();
+    let mut user = use_server_future(move || get_user(id_public))?;
+    let mut name_input = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+
+    let name = match user() {
+        Some(Ok(Some(user))) => user.name,
+        _ => return rsx! {},
+    };
+    // The server might have given the user another name at registration.
+    #[cfg(feature = "web")]
+    crate::components::map::web::set_storage("user_name", &name);
+
+    rsx! {
+        div {
+            id: "profile-editor",
+            p { "You play as {name}" }
+            input {
+                value: "{name_input}",
+                oninput: move |e| {
+                    error.set(validate_name(&e.value(), &[]).err());
+                    name_input.set(e.value());
+                },
+                placeholder: "New name",
+                maxlength: "{NAME_MAX_LEN}",
+            }
+            button {
+                disabled: error().is_some() || name_input().trim().is_empty(),
+                onclick: move |_| async move {
+                    match rename(name_input()).await {
+                        Ok(name) => {
+                            name_input.set(String::new());
+                            user.restart();
+                            if let Some(onrename) = onrename {
+                                onrename.call(name);
+                            }
+                        }
+                        Err(e) => error.set(Some(e)),
+                    }
+                },
+                "Change name"
+            }
+            if let Some(e) = error() {
+                p { style: "color: #e74c3c;", "{e}" }
+            }
+        }
+    }
+}
+
+async fn rename(name: String) -> Result<String, String> {
+    let session = crate::components::map::session()
+        .await
+        .map_err(|e| e.to_string())?;
+    let name = rename_user(session, name)
+        .await
+        .map_err(|e| format!("Couldn't change the name: {e}"))?;
+    tracing::info!("Renamed to {name}");
+    #[cfg(feature = "web")]
+    crate::components::map::web::set_storage("user_name", &name);
+    Ok(name)
+}
@@ -0,0 +1,148 @@
+use flarch::nodeids::U256;
+#[cfg(feature = "server")]
+use hmac::Mac;
+use serde::{Deserialize, Serialize};
+
+/// Proves to the server that the client knows the private ID of a user, without sending
+/// it with every request.
+/// The client gets it from [`crate::components::storage::login`] and sends it to all
+/// server functions which change the user's data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id_public: U256,
+    /// Unix time in milliseconds after which the session is rejected.
+    pub expires: i64,
+    mac: U256,
+}
+
+/// File in the data directory holding the key which signs the sessions.
+#[cfg(feature = "server")]
+const KEY_FILE: &str = "session.key";
+
+#[cfg(feature = "server")]
+static KEY: std::sync::OnceLock<U256> = std::sync::OnceLock::new();
+
+/// Loads the signing key, or creates it on the first start.
+/// Keeping it on disk means the sessions survive a restart of the server.
+#[cfg(feature = "server")]
+pub fn init() -> anyhow::Result<()> {
+    let path = crate::components::config::config().path(KEY_FILE);
+    let key = match std::fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s)
+            .map_err(|e| anyhow::anyhow!("Couldn't parse session key {path}: {e}"))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = U256::rnd();
+            crate::components::storage::write_file(&path, &serde_json::to_string(&key)?)?;
+            key
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let _ = KEY.set(key);
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+impl Session {
+    pub fn new(id_public: U256) -> Self {
+        let config = crate::components::config::config();
+        let expires = flarch::tasks::now() + config.session_hours * 60 * 60 * 1000;
+        Self::signed(Self::key(), id_public, expires)
+    }
+
+    /// Returns the public ID of the user if the session is valid.
+    pub fn verify(&self) -> anyhow::Result<U256> {
+        self.verify_with(Self::key())
+    }
+
+    fn signed(key: &U256, id_public: U256, expires: i64) -> Self {
+        Session {
+            id_public,
+            expires,
+            mac: Self::hmac(key, &id_public, expires)
+                .finalize()
+                .into_bytes()
+                .into(),
+        }
+    }
+
+    fn verify_with(&self, key: &U256) -> anyhow::Result<U256> {
+        if self.expires < flarch::tasks::now() {
+            return Err(anyhow::anyhow!("Session expired"));
+        }
+        Self::hmac(key, &self.id_public, self.expires)
+            .verify_slice(self.mac.as_ref())
+            .map_err(|_| anyhow::anyhow!("Invalid session"))?;
+        Ok(self.id_public)
+    }
+
+    fn key() -> &'static U256 {
+        KEY.get().expect("Session key should be loaded at startup")
+    }
+
+    fn hmac(key: &U256, id_public: &U256, expires: i64) -> hmac::Hmac<sha2::Sha256> {
+        let mut mac =
+            hmac::Hmac::<sha2::Sha256>::new_from_slice(key.as_ref()).expect("Any key length");
+        mac.update(b"warwick-session");
+        mac.update(id_public.as_ref());
+        mac.update(&expires.to_be_bytes());
+        mac
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use flarch::tasks::now;
+
+    use super::*;
+
+    const HOUR_MS: i64 = 60 * 60 * 1000;
+
+    #[test]
+    fn valid() {
+        let (key, id_public) = (U256::rnd(), U256::rnd());
+        let session = Session::signed(&key, id_public, now() + HOUR_MS);
+        assert_eq!(session.verify_with(&key).unwrap(), id_public);
+    }
+
+    #[test]
+    fn expired() {
+        let key = U256::rnd();
+        let session = Session::signed(&key, U256::rnd(), now() - 1);
+        assert_eq!(
+            session.verify_with(&key).unwrap_err().to_string(),
+            "Session expired"
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let key = U256::rnd();
+        let session = Session::signed(&key, U256::rnd(), now() + HOUR_MS);
+        let tampered = [
+            Session {
+                id_public: U256::rnd(),
+                ..session.clone()
+            },
+            Session {
+                expires: session.expires + 100 * HOUR_MS,
+                ..session.clone()
+            },
+            Session {
+                mac: U256::rnd(),
+                ..session.clone()
+            },
+        ];
+        for session in tampered {
+            assert_eq!(
+                session.verify_with(&key).unwrap_err().to_string(),
+                "Invalid session"
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_key() {
+        let session = Session::signed(&U256::rnd(), U256::rnd(), now() + HOUR_MS);
+        assert!(session.verify_with(&U256::rnd()).is_err());
+    }
+}
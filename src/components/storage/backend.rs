@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use dioxus::logger::tracing;
+use flarch::{nodeids::U256, tasks::now};
+
+use super::events::Event;
+use super::public_id;
+use super::{
+    Leaderboard, Message, MessageString, NameChange, PoiSummary, Profile, ProfileMessage,
+    RankedUser, Score, Stats, User, UserPrivate, Visit, LEADERBOARD_PAGE_SIZE, POINTS_COMPLETION,
+    POINTS_FIRST_VISIT, POINTS_MESSAGE, VISIT_INTERVAL_MS,
+};
+use crate::components::profile::{validate_name, NAME_MAX_LEN, RENAMES_PER_DAY};
+
+/// Writes the changes copied by [`StorageBackend::prepare_save`] to disk.
+pub type PendingWrite = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// Where users, messages, and visits are kept.
+/// Backends only implement the primitives, the rules of the game are in the
+/// provided methods, so they behave the same for all backends.
+/// All changes go through [`Event`]s, which [`StorageBackend::apply`] turns into
+/// calls to the primitives.
+pub trait StorageBackend: Send + Sync {
+    fn user(&self, id: &U256) -> Result<Option<UserPrivate>>;
+    /// The user with the public ID. Backends index the public IDs, as this is called
+    /// for every session.
+    fn user_by_public(&self, id_public: &U256) -> Result<Option<UserPrivate>>;
+    /// Inserts or replaces the user.
+    fn put_user(&mut self, user: UserPrivate) -> Result<()>;
+    fn private_users(&self) -> Result<Vec<UserPrivate>>;
+
+    fn push_message(&mut self, msg: Message) -> Result<()>;
+    /// Messages of the POI, oldest first.
+    fn messages(&self, poi: &str) -> Result<Vec<Message>>;
+    fn last_message(&self) -> Result<Option<Message>>;
+    fn message_count(&self) -> Result<usize>;
+
+    fn push_visit(&mut self, visit: Visit) -> Result<()>;
+    /// Visits of the user, oldest first.
+    fn visits(&self, user: &U256) -> Result<Vec<Visit>>;
+
+    /// Copies the pending changes, and returns the write to disk. Called regularly and on
+    /// shutdown with the storage locked, so the slow write runs after the lock is released.
+    fn prepare_save(&self) -> Result<PendingWrite> {
+        Ok(Box::new(|| Ok(())))
+    }
+
+    /// Stores the event. Backends which keep the events override this.
+    fn record(&mut self, event: Event) -> Result<()> {
+        self.apply(&event)
+    }
+
+    /// Applies the rules of the game for the event to the stored state.
+    fn apply(&mut self, event: &Event) -> Result<()> {
+        match event.clone() {
+            Event::UserRegistered { id, name, .. } => {
+                tracing::info!("Adding user {name}/{id}");
+                self.put_user(UserPrivate::new(id, name))
+            }
+            Event::UserRenamed { id, name, time } => {
+                let mut user = self.user_or_new(id)?;
+                let previous = std::mem::replace(&mut user.name, name);
+                user.previous_names.push(NameChange {
+                    name: previous,
+                    until: time,
+                });
+                self.put_user(user)
+            }
+            Event::KeyRegistered { id, key, .. } => {
+                let mut user = self.user_or_new(id)?;
+                user.key = Some(key);
+                self.put_user(user)
+            }
+            Event::MessagePosted { message } => {
+                let first = !self
+                    .messages(&message.poi)?
+                    .iter()
+                    .any(|m| m.sender == message.sender);
+                if first {
+                    let mut private = self.user_or_new(message.sender)?;
+                    private.points += POINTS_MESSAGE;
+                    self.put_user(private)?;
+                }
+                self.push_message(message)
+            }
+            Event::PoiVisited { visit, completes } => {
+                let first = !self.visits(&visit.user)?.iter().any(|v| v.poi == visit.poi);
+                let mut private = self.user_or_new(visit.user)?;
+                if first {
+                    private.points += POINTS_FIRST_VISIT;
+                }
+                if completes && private.completed.is_none() {
+                    private.completed = Some(visit.time);
+                    private.points += POINTS_COMPLETION;
+                }
+                self.push_visit(visit)?;
+                self.put_user(private)
+            }
+        }
+    }
+
+    /// Registers a new user. Existing users keep their name, it only changes
+    /// with [`StorageBackend::rename_user`].
+    /// An invalid, blocked, or taken name is replaced with one derived from the user's ID.
+    fn add_user(&mut self, id: U256, name: String, blocklist: &[String]) -> Result<()> {
+        if self.user(&id)?.is_some() {
+            return Ok(());
+        }
+        let name = match validate_name(&name, blocklist) {
+            Ok(name) if !self.name_taken(&name, &id)? => name,
+            _ => self.fallback_name(&id)?,
+        };
+        self.record(Event::UserRegistered {
+            id,
+            name,
+            time: now(),
+        })
+    }
+
+    /// A valid name from the start of the public ID, for users whose name isn't allowed.
+    /// It gets longer if another user already has it.
+    fn fallback_name(&self, id: &U256) -> Result<String> {
+        const PREFIX: &str = "user-";
+        let hex = hex::encode(public_id(id).to_bytes());
+        for len in 8..=NAME_MAX_LEN - PREFIX.len() {
+            let name = format!("{PREFIX}{}", &hex[..len]);
+            if !self.name_taken(&name, id)? {
+                return Ok(name);
+            }
+        }
+        Err(anyhow!("No free name for the user"))
+    }
+
+    /// Whether another user has the same name, ignoring the case.
+    fn name_taken(&self, name: &str, id: &U256) -> Result<bool> {
+        let name = name.to_lowercase();
+        Ok(self
+            .private_users()?
+            .iter()
+            .any(|u| &u.id_private != id && u.name.to_lowercase() == name))
+    }
+
+    /// Changes the name of an existing user, which must already be validated.
+    fn rename_user(&mut self, id: U256, name: String) -> Result<()> {
+        let user = self.user(&id)?.ok_or(anyhow!("Unknown user"))?;
+        if user.name == name {
+            return Ok(());
+        }
+        if self.name_taken(&name, &id)? {
+            return Err(anyhow!("The name {name} is already taken"));
+        }
+        let time = now();
+        let recent = user
+            .previous_names
+            .iter()
+            .filter(|n| time - n.until < 24 * 60 * 60 * 1000)
+            .count();
+        if recent >= RENAMES_PER_DAY {
+            return Err(anyhow!(
+                "You can only change your name {RENAMES_PER_DAY} times a day"
+            ));
+        }
+        self.record(Event::UserRenamed { id, name, time })
+    }
+
+    // Visits can arrive before the user is stored, so create it if necessary.
+    fn user_or_new(&self, id: U256) -> Result<UserPrivate> {
+        Ok(self
+            .user(&id)?
+            .unwrap_or(UserPrivate::new(id, "Unknown".to_string())))
+    }
+
+    /// Stores the public key of the user, which can't be changed afterwards.
+    fn register_key(&mut self, id: U256, key: U256) -> Result<()> {
+        match self.user(&id)?.and_then(|u| u.key) {
+            None => self.record(Event::KeyRegistered {
+                id,
+                key,
+                time: now(),
+            }),
+            Some(registered) if registered == key => Ok(()),
+            Some(_) => Err(anyhow!("The user has another key")),
+        }
+    }
+
+    /// Only users who checked in at the POI around the time of the message can post there.
+    fn add_message(&mut self, message: Message) -> Result<()> {
+        let present = self
+            .visits(&message.sender)?
+            .iter()
+            .any(|v| v.poi == message.poi && (message.time - v.time).abs() < VISIT_INTERVAL_MS);
+        if !present {
+            return Err(anyhow!("You can only post at a POI you're at"));
+        }
+        if let Some(signature) = &message.signature {
+            if self.user(&message.sender)?.and_then(|u| u.key) != Some(signature.key) {
+                return Err(anyhow!(
+                    "The message isn't signed with the key of the sender"
+                ));
+            }
+        }
+        tracing::info!("Adding message {}/{}", message.poi, message.message);
+        self.record(Event::MessagePosted { message })
+    }
+
+    /// Logs the visit at `time`, which awards the points for a first visit and for
+    /// completing all POIs in `all_pois`.
+    /// Visits queued offline arrive late, so they can be older than the last one.
+    fn add_visit(&mut self, user: U256, poi: String, time: i64, all_pois: &[String]) -> Result<()> {
+        let close = self
+            .visits(&user)?
+            .iter()
+            .any(|v| v.poi == poi && (time - v.time).abs() < VISIT_INTERVAL_MS);
+        if close {
+            return Ok(());
+        }
+        tracing::info!("User {user} visits {poi}");
+        let collected = self.collected(&user)?;
+        let completes = !all_pois.is_empty()
+            && all_pois
+                .iter()
+                .all(|id| id == &poi || collected.iter().any(|(c, _)| c == id));
+        self.record(Event::PoiVisited {
+            visit: Visit { user, poi, time },
+            completes,
+        })
+    }
+
+    /// The POIs visited by the user, with the time of the first visit.
+    fn collected(&self, user: &U256) -> Result<Vec<(String, i64)>> {
+        let mut collected: Vec<(String, i64)> = vec![];
+        for visit in self.visits(user)? {
+            if !collected.iter().any(|(poi, _)| poi == &visit.poi) {
+                collected.push((visit.poi, visit.time));
+            }
+        }
+        Ok(collected)
+    }
+
+    fn score(&self, user: &U256) -> Result<Score> {
+        let private = self.user(user)?;
+        Ok(Score {
+            points: private.as_ref().map(|u| u.points).unwrap_or(0),
+            collected: self.collected(user)?,
+            completed: private.and_then(|u| u.completed),
+        })
+    }
+
+    fn users(&self) -> Result<Vec<User>> {
+        self.private_users()?
+            .iter()
+            .map(|u| {
+                let mut user: User = u.into();
+                user.collected = self.collected(&u.id_private)?.len();
+                Ok(user)
+            })
+            .collect()
+    }
+
+    /// All users by points, then number of collected POIs, then who completed first.
+    fn ranking(&self) -> Result<Vec<User>> {
+        let mut users = self.users()?;
+        users.sort_by(|a, b| {
+            b.points
+                .cmp(&a.points)
+                .then(b.collected.cmp(&a.collected))
+                .then(match (a.completed, b.completed) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                })
+                .then(a.name.cmp(&b.name))
+        });
+        Ok(users)
+    }
+
+    fn leaderboard(&self, page: usize, me: Option<U256>) -> Result<Leaderboard> {
+        let ranked: Vec<RankedUser> = self
+            .ranking()?
+            .into_iter()
+            .enumerate()
+            .map(|(i, user)| RankedUser { rank: i + 1, user })
+            .collect();
+        let pages = ranked.len().div_ceil(LEADERBOARD_PAGE_SIZE).max(1);
+        let page = page.min(pages - 1);
+        Ok(Leaderboard {
+            me: me.and_then(|id| ranked.iter().find(|r| r.user.id_public == id).cloned()),
+            entries: ranked
+                .into_iter()
+                .skip(page * LEADERBOARD_PAGE_SIZE)
+                .take(LEADERBOARD_PAGE_SIZE)
+                .collect(),
+            page,
+            pages,
+        })
+    }
+
+    /// Everything public about a user, with the messages written at one of `pois`.
+    fn profile(&self, id_public: &U256, pois: &[String]) -> Result<Option<Profile>> {
+        let Some(private) = self.user_by_public(id_public)? else {
+            return Ok(None);
+        };
+        let Some((rank, user)) = self
+            .ranking()?
+            .into_iter()
+            .enumerate()
+            .find(|(_, u)| &u.id_public == id_public)
+        else {
+            return Ok(None);
+        };
+        let mut messages = vec![];
+        for poi in pois {
+            messages.extend(
+                self.messages(poi)?
+                    .into_iter()
+                    .filter(|m| m.sender == private.id_private)
+                    .map(|m| ProfileMessage {
+                        poi: m.poi,
+                        time: m.time,
+                        message: m.message,
+                    }),
+            );
+        }
+        messages.sort_by_key(|m| std::cmp::Reverse(m.time));
+        Ok(Some(Profile {
+            rank: rank + 1,
+            user,
+            collected: self.collected(&private.id_private)?,
+            messages,
+            total_pois: pois.len(),
+        }))
+    }
+
+    /// The state of each of `pois` for the user, who doesn't need to exist yet.
+    fn poi_summaries(&self, id_public: &U256, pois: &[String]) -> Result<Vec<PoiSummary>> {
+        let user = self.user_by_public(id_public)?.map(|u| u.id_private);
+        let visits = match &user {
+            Some(user) => self.visits(user)?,
+            None => vec![],
+        };
+        pois.iter()
+            .map(|poi| {
+                let messages = self.messages(poi)?;
+                let visited = visits
+                    .iter()
+                    .filter(|v| &v.poi == poi)
+                    .map(|v| v.time)
+                    .max();
+                // The user's own messages aren't new to them.
+                let newest = messages
+                    .iter()
+                    .filter(|m| Some(m.sender) != user)
+                    .map(|m| m.time)
+                    .max();
+                Ok(PoiSummary {
+                    poi: poi.clone(),
+                    messages: messages.len(),
+                    visited,
+                    new_messages: matches!((visited, newest), (Some(v), Some(n)) if n > v),
+                })
+            })
+            .collect()
+    }
+
+    fn get_messages(&self, poi: &str) -> Result<Vec<MessageString>> {
+        let names = self.names()?;
+        Ok(self
+            .messages(poi)?
+            .iter()
+            .map(|msg| message_string(&names, msg))
+            .collect())
+    }
+
+    fn names(&self) -> Result<HashMap<U256, String>> {
+        Ok(self
+            .private_users()?
+            .into_iter()
+            .map(|u| (u.id_private, u.name))
+            .collect())
+    }
+
+    fn get_stats(&self) -> Result<Stats> {
+        let names = self.names()?;
+        Ok(Stats {
+            last: self
+                .last_message()?
+                .map(|msg| (message_string(&names, &msg), msg.poi.clone())),
+            total_messages: self.message_count()?,
+            total_users: names.len(),
+        })
+    }
+}
+
+fn message_string(names: &HashMap<U256, String>, msg: &Message) -> MessageString {
+    MessageString {
+        sender: names
+            .get(&msg.sender)
+            .cloned()
+            .unwrap_or("Unknown".to_string()),
+        time: msg.time,
+        message: msg.message.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{eventlog::EventLog, json::Storage, sqlite::SqliteStorage};
+    use super::*;
+
+    fn temp_dir() -> String {
+        let dir = std::env::temp_dir().join(format!("warwick-backend-{}", U256::rnd()));
+        dir.to_string_lossy().to_string()
+    }
+
+    fn message(sender: U256, poi: &str, text: &str) -> Message {
+        Message {
+            sender,
+            poi: poi.to_string(),
+            time: now(),
+            message: text.to_string(),
+            signature: None,
+        }
+    }
+
+    /// The rules of the game, which every backend must follow the same way.
+    /// `open` is called again after saving, to check that everything is kept.
+    fn conformance(open: impl Fn() -> Box<dyn StorageBackend>) {
+        let pois = ["hare".to_string(), "lake".to_string()];
+        let (alice, bob) = (U256::rnd(), U256::rnd());
+        let mut s = open();
+
+        let blocklist = ["troll".to_string()];
+        s.add_user(alice, "Alice".into(), &blocklist).unwrap();
+        s.add_user(bob, "alice".into(), &blocklist).unwrap();
+        s.add_user(alice, "Other".into(), &blocklist).unwrap();
+        assert_eq!(s.user(&alice).unwrap().unwrap().name, "Alice");
+        let fallback = |id: &U256, len: usize| {
+            format!("user-{}", &hex::encode(public_id(id).to_bytes())[..len])
+        };
+        assert_eq!(s.user(&bob).unwrap().unwrap().name, fallback(&bob, 8));
+        assert!(validate_name(&fallback(&bob, 8), &blocklist).is_ok());
+        let troll = U256::rnd();
+        // Somebody already took the name the troll would get.
+        s.add_user(U256::rnd(), fallback(&troll, 8), &blocklist)
+            .unwrap();
+        s.add_user(troll, "Trolling".into(), &blocklist).unwrap();
+        assert_eq!(s.user(&troll).unwrap().unwrap().name, fallback(&troll, 9));
+        assert!(s.rename_user(bob, "ALICE".into()).is_err());
+        s.rename_user(bob, "Bob".into()).unwrap();
+        assert_eq!(s.user(&bob).unwrap().unwrap().previous_names.len(), 1);
+        assert_eq!(
+            s.user_by_public(&public_id(&bob)).unwrap().unwrap().name,
+            "Bob"
+        );
+
+        let key = U256::rnd();
+        s.register_key(alice, key).unwrap();
+        s.register_key(alice, key).unwrap();
+        assert!(s.register_key(alice, U256::rnd()).is_err());
+
+        assert!(s.add_message(message(alice, "hare", "too early")).is_err());
+        s.add_visit(alice, "hare".into(), now(), &pois).unwrap();
+        s.add_visit(alice, "hare".into(), now(), &pois).unwrap();
+        s.add_message(message(alice, "hare", "first")).unwrap();
+        // Later than the visit, but written by the visitor.
+        let mut second = message(alice, "hare", "second");
+        second.time += 1000;
+        s.add_message(second).unwrap();
+        assert!(s.add_message(message(bob, "hare", "not there")).is_err());
+        s.add_visit(alice, "lake".into(), now(), &pois).unwrap();
+        // Queued offline, so the check-in arrives late and before the message.
+        let offline = now() - 30 * 60 * 1000;
+        s.add_visit(bob, "lake".into(), offline, &pois).unwrap();
+        s.add_visit(bob, "lake".into(), now(), &pois).unwrap();
+        let mut late = message(bob, "lake", "late");
+        late.time = offline + 1000;
+        s.add_message(late).unwrap();
+
+        let check = |s: &dyn StorageBackend| {
+            let score = s.score(&alice).unwrap();
+            assert_eq!(
+                score.points,
+                2 * POINTS_FIRST_VISIT + POINTS_MESSAGE + POINTS_COMPLETION
+            );
+            assert_eq!(score.collected.len(), 2);
+            assert!(score.completed.is_some());
+            assert_eq!(
+                s.score(&bob).unwrap().points,
+                POINTS_FIRST_VISIT + POINTS_MESSAGE
+            );
+            assert_eq!(s.visits(&bob).unwrap().len(), 1);
+            assert_eq!(s.visits(&alice).unwrap().len(), 2);
+            let messages: Vec<_> = s
+                .messages("hare")
+                .unwrap()
+                .into_iter()
+                .map(|m| m.message)
+                .collect();
+            assert_eq!(messages, ["first", "second"]);
+            assert_eq!(s.message_count().unwrap(), 3);
+            assert_eq!(s.last_message().unwrap().unwrap().message, "second");
+            assert_eq!(s.user(&alice).unwrap().unwrap().key, Some(key));
+            let hare = |id: &U256| {
+                s.poi_summaries(&public_id(id), &pois)
+                    .unwrap()
+                    .into_iter()
+                    .find(|summary| summary.poi == "hare")
+                    .unwrap()
+            };
+            assert_eq!(hare(&alice).messages, 2);
+            assert!(!hare(&alice).new_messages);
+            assert!(hare(&bob).visited.is_none());
+            let ranking: Vec<_> = s.ranking().unwrap().into_iter().map(|u| u.name).collect();
+            assert_eq!(ranking[..2], ["Alice", "Bob"]);
+        };
+        check(&*s);
+        s.prepare_save().unwrap()().unwrap();
+        drop(s);
+        check(&*open());
+    }
+
+    #[test]
+    fn json() {
+        let path = format!("{}/storage.json", temp_dir());
+        conformance(|| Box::new(Storage::new(&path).unwrap()));
+    }
+
+    #[test]
+    fn sqlite() {
+        let path = format!("{}/storage.sqlite", temp_dir());
+        conformance(|| Box::new(SqliteStorage::new(&path).unwrap()));
+    }
+
+    #[test]
+    fn eventlog() {
+        let dir = temp_dir();
+        conformance(|| Box::new(EventLog::new(&dir).unwrap()));
+    }
+}
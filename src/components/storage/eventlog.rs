@@ -0,0 +1,286 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use dioxus::logger::tracing;
+use flarch::nodeids::U256;
+use serde::{Deserialize, Serialize};
+
+use super::backend::PendingWrite;
+use super::events::Event;
+use super::json::{write_file, Storage, StorageData};
+use super::{Message, StorageBackend, UserPrivate, Visit};
+
+/// Number of events after which a new snapshot is written.
+const SNAPSHOT_INTERVAL: u64 = 1000;
+const LOG_FILE: &str = "events.jsonl";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+#[derive(Serialize, Deserialize)]
+struct LoggedEvent {
+    seq: u64,
+    event: Event,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    seq: u64,
+    storage: &'a StorageData,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    seq: u64,
+    storage: serde_json::Value,
+}
+
+/// Appends every change as one line to `events.jsonl`, and rebuilds the state from the
+/// last snapshot and the events after it on startup.
+/// Every [`SNAPSHOT_INTERVAL`] events the state is written to `snapshot.json` and the
+/// log continues in a new file. The old files are kept as the audit trail.
+pub struct EventLog {
+    state: Storage,
+    dir: PathBuf,
+    log: File,
+    seq: u64,
+    snapshot_seq: u64,
+}
+
+impl EventLog {
+    pub fn new(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)?;
+        let (mut state, snapshot_seq) = match fs::read_to_string(dir.join(SNAPSHOT_FILE)) {
+            Ok(s) => {
+                let snapshot: Snapshot = serde_json::from_str(&s)
+                    .map_err(|e| anyhow!("Couldn't parse the snapshot in {dir:?}: {e}"))?;
+                let data = StorageData::from_value(snapshot.storage)?;
+                (Storage::with_data(data, ""), snapshot.seq)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (Storage::empty(""), 0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let log_path = dir.join(LOG_FILE);
+        let mut seq = snapshot_seq;
+        let content = match fs::read_to_string(&log_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut valid_len = 0;
+        for line in content.split_inclusive('\n') {
+            let logged = match serde_json::from_str::<LoggedEvent>(line) {
+                Ok(logged) if line.ends_with('\n') => logged,
+                // A crash during an append can only break the last line.
+                _ if valid_len + line.len() == content.len() => {
+                    tracing::warn!("Dropping incomplete last event in {log_path:?}");
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&log_path)?
+                        .set_len(valid_len as u64)?;
+                    break;
+                }
+                Ok(_) => unreachable!("only the last line can miss its newline"),
+                Err(e) => return Err(anyhow!("Couldn't parse event after #{seq}: {e}")),
+            };
+            valid_len += line.len();
+            // Events before the snapshot are left over from a crash while rotating the log.
+            if logged.seq > snapshot_seq {
+                state.apply(&logged.event)?;
+                seq = logged.seq;
+            }
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        Ok(Self {
+            state,
+            dir,
+            log,
+            seq,
+            snapshot_seq,
+        })
+    }
+
+    fn snapshot(&mut self) -> Result<()> {
+        tracing::info!("Writing snapshot at event #{}", self.seq);
+        self.log.sync_all()?;
+        let snapshot = serde_json::to_string(&SnapshotRef {
+            seq: self.seq,
+            storage: self.state.data(),
+        })?;
+        let snapshot_path = self.dir.join(SNAPSHOT_FILE);
+        write_file(&snapshot_path.to_string_lossy(), &snapshot)?;
+
+        let log_path = self.dir.join(LOG_FILE);
+        fs::rename(
+            &log_path,
+            self.dir.join(format!(
+                "events-{:08}-{:08}.jsonl",
+                self.snapshot_seq + 1,
+                self.seq
+            )),
+        )?;
+        self.log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        self.snapshot_seq = self.seq;
+        Ok(())
+    }
+}
+
+impl StorageBackend for EventLog {
+    fn user(&self, id: &U256) -> Result<Option<UserPrivate>> {
+        self.state.user(id)
+    }
+
+    fn user_by_public(&self, id_public: &U256) -> Result<Option<UserPrivate>> {
+        self.state.user_by_public(id_public)
+    }
+
+    fn put_user(&mut self, user: UserPrivate) -> Result<()> {
+        self.state.put_user(user)
+    }
+
+    fn private_users(&self) -> Result<Vec<UserPrivate>> {
+        self.state.private_users()
+    }
+
+    fn push_message(&mut self, msg: Message) -> Result<()> {
+        self.state.push_message(msg)
+    }
+
+    fn messages(&self, poi: &str) -> Result<Vec<Message>> {
+        self.state.messages(poi)
+    }
+
+    fn last_message(&self) -> Result<Option<Message>> {
+        self.state.last_message()
+    }
+
+    fn message_count(&self) -> Result<usize> {
+        self.state.message_count()
+    }
+
+    fn push_visit(&mut self, visit: Visit) -> Result<()> {
+        self.state.push_visit(visit)
+    }
+
+    fn visits(&self, user: &U256) -> Result<Vec<Visit>> {
+        self.state.visits(user)
+    }
+
+    /// The events are appended right away, this only makes sure they hit the disk.
+    fn prepare_save(&self) -> Result<PendingWrite> {
+        let log = self.log.try_clone()?;
+        Ok(Box::new(move || Ok(log.sync_data()?)))
+    }
+
+    fn record(&mut self, event: Event) -> Result<()> {
+        let seq = self.seq + 1;
+        let line = serde_json::to_string(&LoggedEvent {
+            seq,
+            event: event.clone(),
+        })?;
+        // A single write, so a crash can't interleave half an event with the next one.
+        self.log.write_all(format!("{line}\n").as_bytes())?;
+        self.seq = seq;
+        self.state.apply(&event)?;
+        if self.seq - self.snapshot_seq >= SNAPSHOT_INTERVAL {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flarch::tasks::now;
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("warwick-eventlog-{}", U256::rnd()))
+    }
+
+    fn registered(i: u64) -> Event {
+        Event::UserRegistered {
+            id: U256::rnd(),
+            name: format!("user {i}"),
+            time: now(),
+        }
+    }
+
+    #[test]
+    fn snapshots() -> Result<()> {
+        let dir = temp_dir();
+        let path = dir.to_string_lossy().to_string();
+        let events = SNAPSHOT_INTERVAL + 5;
+        let mut log = EventLog::new(&path)?;
+        for i in 0..events {
+            log.record(registered(i))?;
+        }
+        assert_eq!(log.snapshot_seq, SNAPSHOT_INTERVAL);
+        assert!(dir.join(SNAPSHOT_FILE).exists());
+        let rotated = dir.join(format!("events-{:08}-{:08}.jsonl", 1, SNAPSHOT_INTERVAL));
+        assert_eq!(
+            fs::read_to_string(rotated)?.lines().count() as u64,
+            SNAPSHOT_INTERVAL
+        );
+        assert_eq!(fs::read_to_string(dir.join(LOG_FILE))?.lines().count(), 5);
+        drop(log);
+
+        // The snapshot and the events after it give the whole state.
+        let log = EventLog::new(&path)?;
+        assert_eq!((log.seq, log.snapshot_seq), (events, SNAPSHOT_INTERVAL));
+        assert_eq!(log.private_users()?.len() as u64, events);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_last_event() -> Result<()> {
+        let dir = temp_dir();
+        let path = dir.to_string_lossy().to_string();
+        let log_path = dir.join(LOG_FILE);
+        let mut log = EventLog::new(&path)?;
+        for i in 0..3 {
+            log.record(registered(i))?;
+        }
+        drop(log);
+        let complete = fs::read_to_string(&log_path)?;
+
+        // A crash while appending the fourth event.
+        let line = serde_json::to_string(&LoggedEvent {
+            seq: 4,
+            event: registered(3),
+        })?;
+        fs::write(&log_path, format!("{complete}{}", &line[..line.len() / 2]))?;
+        let mut log = EventLog::new(&path)?;
+        assert_eq!(log.seq, 3);
+        assert_eq!(log.private_users()?.len(), 3);
+        assert_eq!(fs::read_to_string(&log_path)?, complete);
+
+        // New events continue after the last complete one.
+        log.record(registered(4))?;
+        drop(log);
+        let log = EventLog::new(&path)?;
+        assert_eq!(log.seq, 4);
+        assert_eq!(log.private_users()?.len(), 4);
+        drop(log);
+
+        // Only the last line can be incomplete, anything else is corrupted.
+        let content = fs::read_to_string(&log_path)?;
+        fs::write(&log_path, content.replacen("\"seq\":2", "\"seq\":", 1))?;
+        assert!(EventLog::new(&path).is_err());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
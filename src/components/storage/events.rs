@@ -0,0 +1,34 @@
+use flarch::nodeids::U256;
+use serde::{Deserialize, Serialize};
+
+use super::{Message, Visit};
+
+/// Everything that changes the storage. The backends apply them to their state,
+/// and the event log keeps them to rebuild the state and as an audit trail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Event {
+    UserRegistered {
+        id: U256,
+        name: String,
+        time: i64,
+    },
+    UserRenamed {
+        id: U256,
+        name: String,
+        time: i64,
+    },
+    KeyRegistered {
+        id: U256,
+        key: U256,
+        time: i64,
+    },
+    MessagePosted {
+        message: Message,
+    },
+    PoiVisited {
+        visit: Visit,
+        /// Whether this visit completed the catalog at the time of the visit.
+        completes: bool,
+    },
+}
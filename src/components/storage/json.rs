@@ -0,0 +1,391 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::Path,
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use flarch::{nodeids::U256, tasks::now};
+use flmacro::VersionedSerde;
+use serde::{Deserialize, Serialize};
+
+use super::backend::PendingWrite;
+use super::{public_id, Message, StorageBackend, UserPrivate, Visit};
+
+/// Number of backups of the storage file to keep.
+const BACKUPS: usize = 5;
+/// Minimum time between two backups, the first save after a start always backs up.
+const BACKUP_INTERVAL_MS: i64 = 60 * 60 * 1000;
+
+/// Keeps everything in memory and writes it as one JSON file on [`StorageBackend::prepare_save`].
+#[derive(Debug)]
+pub struct Storage {
+    data: StorageData,
+    /// The private IDs by public ID, which isn't stored.
+    by_public: HashMap<U256, U256>,
+    path: String,
+    last_backup: AtomicI64,
+}
+
+/// The content of the storage file.
+/// To change a field, copy the current fields to a new `StorageDataVn`, add it at the end
+/// of `versions`, and move the `From` implementation into the current version to it.
+#[derive(VersionedSerde, Debug, Clone)]
+#[versions = "[StorageDataV1, StorageDataV2]"]
+pub struct StorageData {
+    messages: HashMap<String, Vec<Message>>,
+    private_users: HashMap<U256, UserPrivate>,
+    visits: Vec<Visit>,
+}
+
+/// The unversioned format from before the POIs had IDs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StorageDataV1 {
+    messages: HashMap<usize, Vec<Message>>,
+    private_users: HashMap<U256, UserPrivate>,
+}
+
+/// The unversioned format with POI IDs, and later the visits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageDataV2 {
+    messages: HashMap<String, Vec<Message>>,
+    private_users: HashMap<U256, UserPrivate>,
+    #[serde(default)]
+    visits: Vec<Visit>,
+}
+
+impl From<StorageDataV1> for StorageDataV2 {
+    fn from(value: StorageDataV1) -> Self {
+        // The messages already got their POI ID when migrating from MessageV1.
+        let mut messages: HashMap<String, Vec<Message>> = HashMap::new();
+        for msg in value.messages.into_values().flatten() {
+            messages.entry(msg.poi.clone()).or_default().push(msg);
+        }
+        StorageDataV2 {
+            messages,
+            private_users: value.private_users,
+            visits: vec![],
+        }
+    }
+}
+
+impl From<StorageDataV2> for StorageData {
+    fn from(value: StorageDataV2) -> Self {
+        StorageData {
+            messages: value.messages,
+            private_users: value.private_users,
+            visits: value.visits,
+        }
+    }
+}
+
+impl StorageData {
+    pub fn empty() -> Self {
+        StorageData {
+            messages: HashMap::new(),
+            private_users: HashMap::new(),
+            visits: vec![],
+        }
+    }
+
+    /// Reads any version of the storage file.
+    /// The versioned formats are tagged with their version, the older ones are
+    /// recognized by their fields.
+    pub fn from_value(value: serde_json::Value) -> Result<Self> {
+        let versioned = value
+            .as_object()
+            .is_some_and(|o| o.keys().any(|k| k.starts_with("StorageDataV")));
+        if versioned {
+            return Ok(serde_json::from_value(value)?);
+        }
+        if let Ok(v1) = serde_json::from_value::<StorageDataV1>(value.clone()) {
+            return Ok(StorageDataV2::from(v1).into());
+        }
+        Ok(serde_json::from_value::<StorageDataV2>(value)?.into())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} users, {} messages, {} visits",
+            self.private_users.len(),
+            self.messages.values().map(|msgs| msgs.len()).sum::<usize>(),
+            self.visits.len()
+        )
+    }
+}
+
+impl Storage {
+    pub fn empty(path: &str) -> Self {
+        Self::with_data(StorageData::empty(), path)
+    }
+
+    pub fn with_data(data: StorageData, path: &str) -> Self {
+        let by_public = data
+            .private_users
+            .keys()
+            .map(|id| (public_id(id), *id))
+            .collect();
+        Self {
+            data,
+            by_public,
+            path: path.to_string(),
+            last_backup: AtomicI64::new(0),
+        }
+    }
+
+    /// Loads the storage from `path`, or starts empty if there is no file yet.
+    /// A file which can't be parsed is an error, as starting empty would overwrite it.
+    pub fn new(path: &str) -> Result<Self> {
+        let file = match fs::read_to_string(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::empty(path)),
+            Err(e) => return Err(anyhow!("Couldn't read {path}: {e}")),
+        };
+        match StorageData::from_json(&file) {
+            Ok(data) => Ok(Self::with_data(data, path)),
+            Err(e) => {
+                let corrupt = format!("{path}.corrupt-{}", now());
+                fs::copy(path, &corrupt)?;
+                Err(anyhow!(
+                    "Couldn't parse {path}: {e}. Copied it to {corrupt}, \
+                    restore one of the {path}.N backups or remove it to start empty."
+                ))
+            }
+        }
+    }
+
+    pub fn data(&self) -> &StorageData {
+        &self.data
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.data)?)
+    }
+}
+
+impl StorageBackend for Storage {
+    fn user(&self, id: &U256) -> Result<Option<UserPrivate>> {
+        Ok(self.data.private_users.get(id).cloned())
+    }
+
+    fn user_by_public(&self, id_public: &U256) -> Result<Option<UserPrivate>> {
+        match self.by_public.get(id_public) {
+            Some(id) => self.user(id),
+            None => Ok(None),
+        }
+    }
+
+    fn put_user(&mut self, user: UserPrivate) -> Result<()> {
+        self.by_public.insert(user.public(), user.id_private);
+        self.data.private_users.insert(user.id_private, user);
+        Ok(())
+    }
+
+    fn private_users(&self) -> Result<Vec<UserPrivate>> {
+        Ok(self.data.private_users.values().cloned().collect())
+    }
+
+    fn push_message(&mut self, msg: Message) -> Result<()> {
+        self.data
+            .messages
+            .entry(msg.poi.clone())
+            .or_default()
+            .push(msg);
+        Ok(())
+    }
+
+    fn messages(&self, poi: &str) -> Result<Vec<Message>> {
+        Ok(self.data.messages.get(poi).cloned().unwrap_or_default())
+    }
+
+    fn last_message(&self) -> Result<Option<Message>> {
+        Ok(self
+            .data
+            .messages
+            .values()
+            .flatten()
+            .max_by_key(|msg| msg.time)
+            .cloned())
+    }
+
+    fn message_count(&self) -> Result<usize> {
+        Ok(self.data.messages.values().map(|msgs| msgs.len()).sum())
+    }
+
+    fn push_visit(&mut self, visit: Visit) -> Result<()> {
+        self.data.visits.push(visit);
+        Ok(())
+    }
+
+    fn visits(&self, user: &U256) -> Result<Vec<Visit>> {
+        Ok(self
+            .data
+            .visits
+            .iter()
+            .filter(|v| &v.user == user)
+            .cloned()
+            .collect())
+    }
+
+    fn prepare_save(&self) -> Result<PendingWrite> {
+        let backup = now() - self.last_backup.load(Ordering::Acquire) > BACKUP_INTERVAL_MS;
+        if backup {
+            self.last_backup.store(now(), Ordering::Release);
+        }
+        let (path, json) = (self.path.clone(), self.to_json()?);
+        Ok(Box::new(move || {
+            if backup {
+                rotate_backups(&path)?;
+            }
+            write_file(&path, &json)
+        }))
+    }
+}
+
+/// Writes to a temporary file first, so a crash or a full disk never leaves a
+/// truncated file behind.
+pub fn write_file(path: &str, contents: &str) -> Result<()> {
+    let tmp = format!("{path}.tmp");
+    let path = Path::new(path);
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    // Make sure the rename itself survives a crash.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Keeps the last [`BACKUPS`] versions of the file as `path.1` (newest) to `path.N`.
+pub fn rotate_backups(path: &str) -> Result<()> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    for i in (1..BACKUPS).rev() {
+        let from = format!("{path}.{i}");
+        if Path::new(&from).exists() {
+            fs::rename(&from, format!("{path}.{}", i + 1))?;
+        }
+    }
+    fs::copy(path, format!("{path}.1"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::identity::message_digest;
+
+    const ALICE: &str = "2bd806c97f0e00af1a1fc3328fa763a9269723c8db8fac4f93af71db186d6e90";
+    const BOB: &str = "81b637d8fcd2c6da6359e6963113a1170de795e4b725b84d1e0b4cfd9ec58ce9";
+
+    fn fixture(json: &str) -> Storage {
+        let data = StorageData::from_json(json).expect("Should migrate the fixture");
+        // The current version must survive a round trip.
+        let json = serde_json::to_string(&data).unwrap();
+        Storage::with_data(StorageData::from_json(&json).unwrap(), "")
+    }
+
+    fn user(s: &Storage, id: &str) -> UserPrivate {
+        s.user(&id.parse().unwrap())
+            .unwrap()
+            .expect("Should have the user")
+    }
+
+    /// The users and messages which are in every fixture.
+    fn check_messages(s: &Storage) {
+        assert_eq!(s.private_users().unwrap().len(), 2);
+        assert_eq!(user(s, ALICE).name, "alice");
+        assert_eq!(user(s, BOB).name, "bob");
+        let first = s.messages("lets-not-be-stupid").unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].sender, ALICE.parse().unwrap());
+        assert_eq!(first[0].message, "Hello from the first POI");
+        let hare = s.messages("hare").unwrap();
+        assert_eq!(hare.len(), 1);
+        assert_eq!(hare[0].sender, BOB.parse().unwrap());
+        assert_eq!(s.message_count().unwrap(), 2);
+    }
+
+    /// The visits and points from the fixtures with visits.
+    fn check_visits(s: &Storage) {
+        assert_eq!(s.data().visits.len(), 2);
+        assert_eq!(s.collected(&ALICE.parse().unwrap()).unwrap().len(), 1);
+        assert_eq!(s.visits(&BOB.parse().unwrap()).unwrap()[0].poi, "hare");
+        assert_eq!(user(s, ALICE).points, 12);
+    }
+
+    #[test]
+    fn storage_v1() {
+        let s = fixture(include_str!("../../../data/fixtures/storage-v1.json"));
+        check_messages(&s);
+        // The POI indexes are mapped to the IDs.
+        assert!(s
+            .data()
+            .messages
+            .keys()
+            .all(|poi| poi.parse::<usize>().is_err()));
+        assert!(s.data().visits.is_empty());
+    }
+
+    #[test]
+    fn storage_v2() {
+        let s = fixture(include_str!("../../../data/fixtures/storage-v2.json"));
+        check_messages(&s);
+        check_visits(&s);
+    }
+
+    #[test]
+    fn storage_v3() {
+        let s = fixture(include_str!("../../../data/fixtures/storage-v3.json"));
+        check_messages(&s);
+        check_visits(&s);
+    }
+
+    #[test]
+    fn storage_v3_renamed() {
+        let s = fixture(include_str!(
+            "../../../data/fixtures/storage-v3-renamed.json"
+        ));
+        check_messages(&s);
+        check_visits(&s);
+        let alice = user(&s, ALICE);
+        assert!(alice.key.is_some());
+        assert_eq!(alice.previous_names.len(), 1);
+        assert_eq!(alice.previous_names[0].name, "alicia");
+        assert!(user(&s, BOB).previous_names.is_empty());
+    }
+
+    #[test]
+    fn storage_v3_signed() {
+        let s = fixture(include_str!(
+            "../../../data/fixtures/storage-v3-signed.json"
+        ));
+        check_messages(&s);
+        check_visits(&s);
+        for msg in s.data().messages.values().flatten() {
+            let signature = msg.signature.clone().expect("Should keep the signature");
+            let sender = s.user(&msg.sender).unwrap().unwrap();
+            assert_eq!(sender.key, Some(signature.key));
+            let digest = message_digest(&public_id(&msg.sender), &msg.poi, msg.time, &msg.message);
+            signature
+                .verify(&digest)
+                .expect("Should keep a valid signature");
+        }
+    }
+}
@@ -0,0 +1,773 @@
+#[cfg(feature = "server")]
+use anyhow::anyhow;
+use anyhow::Result;
+#[cfg(feature = "server")]
+use dioxus::logger::tracing;
+use dioxus::prelude::*;
+use flarch::nodeids::U256;
+use flmacro::VersionedSerde;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use crate::components::config::config;
+use crate::components::geo::Fix;
+#[cfg(feature = "server")]
+use crate::components::identity::message_digest;
+use crate::components::identity::MessageSignature;
+#[cfg(feature = "server")]
+use crate::components::identity::{MessageExport, SenderKey, SignedMessage};
+#[cfg(feature = "server")]
+use crate::components::poi::load_catalog;
+#[cfg(feature = "server")]
+use crate::components::poi::{legacy_id, MAX_ACCURACY_M};
+#[cfg(feature = "server")]
+use crate::components::profile::validate_name;
+use crate::components::session::Session;
+
+#[cfg(feature = "server")]
+mod backend;
+#[cfg(feature = "server")]
+mod eventlog;
+#[cfg(feature = "server")]
+mod events;
+#[cfg(feature = "server")]
+mod json;
+#[cfg(feature = "server")]
+mod sqlite;
+
+#[cfg(feature = "server")]
+pub use backend::StorageBackend;
+#[cfg(feature = "server")]
+pub use json::write_file;
+
+/// Reads storage files of any version and prints what they contain, to check that
+/// old files still migrate.
+#[cfg(feature = "server")]
+pub fn check(paths: &[String]) -> Result<()> {
+    if paths.is_empty() {
+        return Err(anyhow!("Usage: check-storage <storage.json>..."));
+    }
+    for path in paths {
+        let data = json::StorageData::from_json(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Couldn't migrate {path}: {e}"))?;
+        println!("{path}: {}", data.summary());
+    }
+    Ok(())
+}
+
+/// Points for the first visit of a POI.
+#[cfg(feature = "server")]
+pub const POINTS_FIRST_VISIT: usize = 10;
+/// Points for the first message at a POI.
+#[cfg(feature = "server")]
+pub const POINTS_MESSAGE: usize = 2;
+/// Bonus once all POIs have been visited.
+#[cfg(feature = "server")]
+pub const POINTS_COMPLETION: usize = 50;
+#[cfg(feature = "server")]
+pub const LEADERBOARD_PAGE_SIZE: usize = 10;
+/// Repeated check-ins at the same POI are only logged once per this interval.
+pub const VISIT_INTERVAL_MS: i64 = 60 * 60 * 1000;
+/// Messages and check-ins have the time of the client, which may be off by this much.
+#[cfg(feature = "server")]
+pub const CLIENT_CLOCK_SKEW_MS: i64 = 5 * 60 * 1000;
+/// Messages and check-ins made offline are queued by the client, and accepted for this long.
+pub const OFFLINE_MAX_AGE_MS: i64 = VISIT_INTERVAL_MS;
+
+#[cfg(feature = "server")]
+#[derive(VersionedSerde, Debug, Clone)]
+#[versions = "[MessageV1, MessageV2]"]
+pub struct Message {
+    pub sender: U256,
+    pub poi: String,
+    pub time: i64,
+    pub message: String,
+    pub signature: Option<MessageSignature>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageV1 {
+    pub sender: U256,
+    pub poi: usize,
+    pub time: i64,
+    pub message: String,
+}
+
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageV2 {
+    pub sender: U256,
+    pub poi: String,
+    pub time: i64,
+    pub message: String,
+}
+
+#[cfg(feature = "server")]
+impl From<MessageV1> for MessageV2 {
+    fn from(value: MessageV1) -> Self {
+        MessageV2 {
+            sender: value.sender,
+            poi: legacy_id(value.poi),
+            time: value.time,
+            message: value.message,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<MessageV2> for Message {
+    fn from(value: MessageV2) -> Self {
+        Message {
+            sender: value.sender,
+            poi: value.poi,
+            time: value.time,
+            message: value.message,
+            signature: None,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(VersionedSerde, Debug, Clone)]
+pub struct Visit {
+    pub user: U256,
+    pub poi: String,
+    pub time: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Score {
+    pub points: usize,
+    /// POI IDs with the time of the first visit.
+    pub collected: Vec<(String, i64)>,
+    pub completed: Option<i64>,
+}
+
+/// The session of a user who logged in, and the name the user is registered with,
+/// which differs from the requested one if that wasn't allowed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggedIn {
+    pub session: Session,
+    pub name: String,
+}
+
+/// What the server knows about an account restored from its recovery code.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RestoredAccount {
+    pub name: String,
+    pub score: Score,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Stats {
+    pub last: Option<(MessageString, String)>,
+    pub total_messages: usize,
+    pub total_users: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageString {
+    pub sender: String,
+    pub time: i64,
+    pub message: String,
+}
+
+#[derive(VersionedSerde, Debug, Clone)]
+#[versions = "[UserPrivateV1, UserPrivateV2, UserPrivateV3]"]
+pub struct UserPrivate {
+    name: String,
+    points: usize,
+    id_private: U256,
+    /// When the user visited all POIs.
+    completed: Option<i64>,
+    /// The public key the user signs messages with.
+    key: Option<U256>,
+    /// Earlier names of the user, for moderation.
+    previous_names: Vec<NameChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NameChange {
+    pub name: String,
+    /// When the user changed to another name.
+    pub until: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserPrivateV1 {
+    name: String,
+    points: usize,
+    id_private: U256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserPrivateV2 {
+    name: String,
+    points: usize,
+    id_private: U256,
+    completed: Option<i64>,
+}
+
+impl From<UserPrivateV1> for UserPrivateV2 {
+    fn from(value: UserPrivateV1) -> Self {
+        UserPrivateV2 {
+            name: value.name,
+            points: value.points,
+            id_private: value.id_private,
+            completed: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserPrivateV3 {
+    name: String,
+    points: usize,
+    id_private: U256,
+    completed: Option<i64>,
+    key: Option<U256>,
+}
+
+impl From<UserPrivateV2> for UserPrivateV3 {
+    fn from(value: UserPrivateV2) -> Self {
+        UserPrivateV3 {
+            name: value.name,
+            points: value.points,
+            id_private: value.id_private,
+            completed: value.completed,
+            key: None,
+        }
+    }
+}
+
+impl From<UserPrivateV3> for UserPrivate {
+    fn from(value: UserPrivateV3) -> Self {
+        UserPrivate {
+            name: value.name,
+            points: value.points,
+            id_private: value.id_private,
+            completed: value.completed,
+            key: value.key,
+            previous_names: vec![],
+        }
+    }
+}
+
+impl UserPrivate {
+    pub fn new(id_private: U256, name: String) -> Self {
+        UserPrivate {
+            name,
+            points: 0,
+            id_private,
+            completed: None,
+            key: None,
+            previous_names: vec![],
+        }
+    }
+
+    pub fn public(&self) -> U256 {
+        public_id(&self.id_private)
+    }
+}
+
+/// The ID under which a user is shown to others.
+/// Clients use it to find themselves, so they never need to send their private ID.
+pub fn public_id(id_private: &U256) -> U256 {
+    U256::hash_domain_parts("user", &[id_private.as_ref()])
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub points: usize,
+    pub id_public: U256,
+    #[serde(default)]
+    pub collected: usize,
+    #[serde(default)]
+    pub completed: Option<i64>,
+}
+
+impl From<&UserPrivate> for User {
+    fn from(value: &UserPrivate) -> Self {
+        User {
+            id_public: value.public(),
+            name: value.name.clone(),
+            points: value.points,
+            collected: 0,
+            completed: value.completed,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RankedUser {
+    pub rank: usize,
+    pub user: User,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub rank: usize,
+    pub user: User,
+    /// POI IDs with the time of the first visit.
+    pub collected: Vec<(String, i64)>,
+    /// The messages of the user, newest first.
+    pub messages: Vec<ProfileMessage>,
+    pub total_pois: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileMessage {
+    pub poi: String,
+    pub time: i64,
+    pub message: String,
+}
+
+/// What the map shows about a POI for a given user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoiSummary {
+    pub poi: String,
+    pub messages: usize,
+    /// The last visit of the user.
+    pub visited: Option<i64>,
+    /// Whether there are messages the user didn't see at the last visit.
+    pub new_messages: bool,
+}
+
+/// A user with the name history, only shown to the organizers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModeratedUser {
+    pub user: User,
+    pub previous_names: Vec<NameChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Leaderboard {
+    pub entries: Vec<RankedUser>,
+    /// The entry of the requesting user, even if it's not on this page.
+    pub me: Option<RankedUser>,
+    pub page: usize,
+    pub pages: usize,
+}
+
+#[cfg(feature = "server")]
+const STORAGE_FILE: &str = "storage.json";
+#[cfg(feature = "server")]
+const SQLITE_FILE: &str = "storage.sqlite";
+#[cfg(feature = "server")]
+const EVENTS_DIR: &str = "events";
+/// How often changes are written to disk.
+#[cfg(feature = "server")]
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The storage is loaded once and shared by all server functions.
+/// Every mutation holds the write lock from reading to modifying, so concurrent
+/// calls can't lose each other's changes.
+/// Changes are written back by a background task, and by [`flush`] on shutdown.
+#[cfg(feature = "server")]
+static STORAGE: tokio::sync::OnceCell<tokio::sync::RwLock<Box<dyn StorageBackend>>> =
+    tokio::sync::OnceCell::const_new();
+/// Bumped by every successful mutation while holding the write lock.
+#[cfg(feature = "server")]
+static GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+/// Serializes the flushes and holds the generation written last, so an older snapshot
+/// never overwrites a newer one.
+#[cfg(feature = "server")]
+static SAVED: tokio::sync::Mutex<u64> = tokio::sync::Mutex::const_new(0);
+
+/// Opens the configured backend in the data directory.
+#[cfg(feature = "server")]
+async fn load() -> Result<tokio::sync::RwLock<Box<dyn StorageBackend>>> {
+    let config = config();
+    let backend: Box<dyn StorageBackend> = match config.storage.as_str() {
+        "json" => Box::new(json::Storage::new(&config.path(STORAGE_FILE))?),
+        "sqlite" => Box::new(sqlite::SqliteStorage::new(&config.path(SQLITE_FILE))?),
+        "eventlog" => Box::new(eventlog::EventLog::new(&config.path(EVENTS_DIR))?),
+        other => return Err(anyhow!("Unknown storage backend {other}")),
+    };
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+            if let Err(e) = flush().await {
+                tracing::error!("Couldn't write storage: {e:?}");
+            }
+        }
+    });
+    Ok(tokio::sync::RwLock::new(backend))
+}
+
+/// Loads the storage at startup, so the server refuses to start if it's unreadable.
+#[cfg(feature = "server")]
+pub async fn init() -> Result<()> {
+    STORAGE.get_or_try_init(load).await.map(|_| ())
+}
+
+#[cfg(feature = "server")]
+async fn storage() -> &'static tokio::sync::RwLock<Box<dyn StorageBackend>> {
+    STORAGE
+        .get_or_try_init(load)
+        .await
+        .expect("Should get storage")
+}
+
+/// Writes the storage to disk if it changed since the last flush.
+#[cfg(feature = "server")]
+pub async fn flush() -> Result<()> {
+    use std::sync::atomic::Ordering;
+
+    let Some(storage) = STORAGE.get() else {
+        return Ok(());
+    };
+    let mut saved = SAVED.lock().await;
+    let (generation, write) = {
+        let s = storage.read().await;
+        // No mutation can happen while the read lock is held, so the generation
+        // matches the copied data.
+        let generation = GENERATION.load(Ordering::Acquire);
+        if generation == *saved {
+            return Ok(());
+        }
+        (generation, s.prepare_save()?)
+    };
+    // Mutations continue while the copy is written.
+    tokio::task::spawn_blocking(write).await??;
+    *saved = generation;
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+async fn with_storage<T, U: FnOnce(&dyn StorageBackend) -> Result<T>>(
+    f: U,
+) -> Result<T, ServerFnError> {
+    let s = storage().await.read().await;
+    f(&**s).map_err(ServerFnError::new)
+}
+
+#[cfg(feature = "server")]
+async fn with_storage_mut<T, U: FnOnce(&mut dyn StorageBackend) -> Result<T>>(
+    f: U,
+) -> Result<T, ServerFnError> {
+    let mut s = storage().await.write().await;
+    let t = f(&mut **s).map_err(ServerFnError::new)?;
+    GENERATION.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+    Ok(t)
+}
+/// Refuses times from the future, and from before the client could have been offline.
+#[cfg(feature = "server")]
+fn check_client_time(time: i64) -> Result<(), ServerFnError> {
+    let age = flarch::tasks::now() - time;
+    if age < -CLIENT_CLOCK_SKEW_MS {
+        return Err(ServerFnError::new("The time is off, check your clock"));
+    }
+    if age > OFFLINE_MAX_AGE_MS + CLIENT_CLOCK_SKEW_MS {
+        return Err(ServerFnError::new("This was made too long ago"));
+    }
+    Ok(())
+}
+
+/// The private ID of the user holding the session.
+#[cfg(feature = "server")]
+async fn session_user(session: &Session) -> Result<U256, ServerFnError> {
+    let id_public = session.verify().map_err(ServerFnError::new)?;
+    with_storage(|s| {
+        s.user_by_public(&id_public)?
+            .map(|u| u.id_private)
+            .ok_or(anyhow!("Unknown user"))
+    })
+    .await
+}
+
+#[server]
+pub async fn get_messages(poi: String) -> Result<Vec<MessageString>, ServerFnError> {
+    with_storage(|s| s.get_messages(&poi)).await
+}
+
+#[server]
+pub async fn get_users() -> Result<Vec<User>, ServerFnError> {
+    with_storage(|s| s.users()).await
+}
+
+#[server]
+pub async fn get_user(id_public: U256) -> Result<Option<User>, ServerFnError> {
+    with_storage(|s| Ok(s.users()?.into_iter().find(|u| u.id_public == id_public))).await
+}
+
+/// The public profile of a user, `None` if there is no such user.
+#[server]
+pub async fn get_profile(id_public: U256) -> Result<Option<Profile>, ServerFnError> {
+    let catalog = load_catalog().map_err(ServerFnError::new)?;
+    let pois: Vec<String> = catalog.pois.iter().map(|p| p.id.clone()).collect();
+    with_storage(|s| s.profile(&id_public, &pois)).await
+}
+
+#[server]
+pub async fn get_leaderboard(
+    page: usize,
+    id_public: Option<U256>,
+) -> Result<Leaderboard, ServerFnError> {
+    if !config().settings.features.leaderboard {
+        return Err(ServerFnError::new("The leaderboard is disabled"));
+    }
+    with_storage(|s| s.leaderboard(page, id_public)).await
+}
+
+#[server]
+pub async fn add_message(
+    session: Session,
+    poi: String,
+    msg: String,
+    time: i64,
+    signature: MessageSignature,
+) -> Result<(), ServerFnError> {
+    let settings = &config().settings;
+    if !settings.features.messages {
+        return Err(ServerFnError::new("Messages are disabled"));
+    }
+    if msg.trim().is_empty() || msg.chars().count() > settings.max_message_len {
+        return Err(ServerFnError::new(format!(
+            "Messages must have between 1 and {} characters",
+            settings.max_message_len
+        )));
+    }
+    let catalog = load_catalog().map_err(ServerFnError::new)?;
+    if catalog.get(&poi).is_none() {
+        return Err(ServerFnError::new(format!("Unknown POI {poi}")));
+    }
+    check_client_time(time)?;
+    let user_private = session_user(&session).await?;
+    signature
+        .verify(&message_digest(&session.id_public, &poi, time, &msg))
+        .map_err(ServerFnError::new)?;
+    with_storage_mut(|s| {
+        s.add_message(Message {
+            sender: user_private,
+            poi,
+            time,
+            message: msg,
+            signature: Some(signature),
+        })
+    })
+    .await
+}
+
+/// The only call which takes the private ID: it registers the user and its public key
+/// if necessary, and returns the session for all other calls.
+#[server]
+pub async fn login(user_private: U256, name: String, key: U256) -> Result<LoggedIn, ServerFnError> {
+    let name = with_storage_mut(|s| {
+        s.add_user(user_private, name, &config().name_blocklist)?;
+        s.register_key(user_private, key)?;
+        Ok(s.user(&user_private)?.ok_or(anyhow!("Unknown user"))?.name)
+    })
+    .await?;
+    Ok(LoggedIn {
+        session: Session::new(public_id(&user_private)),
+        name,
+    })
+}
+
+/// A new session for the user of a session which is still valid, so the client doesn't
+/// have to send the private ID again.
+#[server]
+pub async fn renew_session(session: Session) -> Result<Session, ServerFnError> {
+    let user_private = session_user(&session).await?;
+    Ok(Session::new(public_id(&user_private)))
+}
+
+/// Changes the name of the user, and returns it as stored.
+#[server]
+pub async fn rename_user(session: Session, name: String) -> Result<String, ServerFnError> {
+    let name = validate_name(&name, &config().name_blocklist).map_err(ServerFnError::new)?;
+    let user_private = session_user(&session).await?;
+    with_storage_mut(|s| s.rename_user(user_private, name.clone())).await?;
+    Ok(name)
+}
+
+/// All users with their earlier names, for the admin page.
+#[server]
+pub async fn get_moderation(admin_token: String) -> Result<Vec<ModeratedUser>, ServerFnError> {
+    let expected = &config().admin_token;
+    if expected.is_empty() || &admin_token != expected {
+        return Err(ServerFnError::new("Wrong admin token"));
+    }
+    with_storage(|s| {
+        Ok(s.private_users()?
+            .into_iter()
+            .map(|u| ModeratedUser {
+                user: (&u).into(),
+                previous_names: u.previous_names,
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Confirms that the account of a recovery code exists, before the client switches to it.
+#[server]
+pub async fn restore_account(
+    user_private: U256,
+    key: U256,
+) -> Result<RestoredAccount, ServerFnError> {
+    with_storage(|s| {
+        let user = s.user(&user_private)?.ok_or(anyhow!("Unknown account"))?;
+        // Accounts from before the keys get the key of the recovery code on login.
+        if user.key.is_some_and(|k| k != key) {
+            return Err(anyhow!("The recovery code doesn't match the account"));
+        }
+        Ok(RestoredAccount {
+            name: user.name,
+            score: s.score(&user_private)?,
+        })
+    })
+    .await
+}
+
+/// All messages with their signatures and the keys of their senders, so they can be
+/// verified without the server with `verify-messages`.
+#[cfg(feature = "server")]
+#[server(endpoint = "export_messages")]
+pub async fn export_messages() -> Result<MessageExport, ServerFnError> {
+    let catalog = load_catalog().map_err(ServerFnError::new)?;
+    with_storage(|s| {
+        let mut export = MessageExport::default();
+        for poi in &catalog.pois {
+            for m in s.messages(&poi.id)? {
+                let sender = public_id(&m.sender);
+                if !export.keys.iter().any(|k| k.sender == sender) {
+                    if let Some(key) = s.user(&m.sender)?.and_then(|u| u.key) {
+                        export.keys.push(SenderKey { sender, key });
+                    }
+                }
+                export.messages.push(SignedMessage {
+                    sender,
+                    poi: m.poi,
+                    time: m.time,
+                    message: m.message,
+                    signature: m.signature,
+                });
+            }
+        }
+        Ok(export)
+    })
+    .await
+}
+
+/// Checks in at `time`, which is earlier if the client queued the check-in offline.
+#[server]
+pub async fn check_in(
+    session: Session,
+    poi: String,
+    fix: Fix,
+    time: i64,
+) -> Result<Score, ServerFnError> {
+    let catalog = load_catalog().map_err(ServerFnError::new)?;
+    let Some(target) = catalog.get(&poi) else {
+        return Err(ServerFnError::new(format!("Unknown POI {poi}")));
+    };
+    if fix.accuracy_m > MAX_ACCURACY_M || !target.contains(&fix) {
+        return Err(ServerFnError::new(format!("Not at POI {poi}")));
+    }
+    check_client_time(time)?;
+    let user_private = session_user(&session).await?;
+    let all_pois: Vec<String> = catalog.pois.iter().map(|p| p.id.clone()).collect();
+    with_storage_mut(|s| {
+        s.add_visit(user_private, poi, time, &all_pois)?;
+        s.score(&user_private)
+    })
+    .await
+}
+
+#[server]
+pub async fn get_score(id_public: U256) -> Result<Score, ServerFnError> {
+    with_storage(|s| match s.user_by_public(&id_public)? {
+        Some(user) => s.score(&user.id_private),
+        None => Ok(Score {
+            points: 0,
+            collected: vec![],
+            completed: None,
+        }),
+    })
+    .await
+}
+
+#[server]
+pub async fn get_stats() -> Result<Stats, ServerFnError> {
+    with_storage(|s| s.get_stats()).await
+}
+
+/// The visits and messages of all POIs, as seen by the user.
+#[server]
+pub async fn get_poi_summaries(id_public: U256) -> Result<Vec<PoiSummary>, ServerFnError> {
+    let catalog = load_catalog().map_err(ServerFnError::new)?;
+    let pois: Vec<String> = catalog.pois.iter().map(|p| p.id.clone()).collect();
+    with_storage(|s| s.poi_summaries(&id_public, &pois)).await
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    /// Hundreds of concurrent mutations through the shared storage, which must all
+    /// survive a flush and a reload.
+    /// The storage and the configuration are global, so this is the only test using them.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_mutations() {
+        const USERS: usize = 300;
+        let dir = std::env::temp_dir().join(format!("warwick-storage-{}", U256::rnd()));
+        std::env::set_var("WARWICK_DATA_DIR", &dir);
+        std::env::set_var("WARWICK_STORAGE", "json");
+        init().await.expect("Should load the storage");
+
+        let pois = vec!["hare".to_string(), "lake".to_string()];
+        let tasks: Vec<_> = (0..USERS)
+            .map(|i| {
+                let pois = pois.clone();
+                tokio::spawn(async move {
+                    let id = U256::rnd();
+                    with_storage_mut(|s| s.add_user(id, format!("user {i}"), &[])).await?;
+                    with_storage_mut(|s| {
+                        s.add_visit(id, "hare".into(), flarch::tasks::now(), &pois)
+                    })
+                    .await?;
+                    with_storage_mut(|s| {
+                        s.add_message(Message {
+                            sender: id,
+                            poi: "hare".into(),
+                            time: flarch::tasks::now(),
+                            message: format!("message {i}"),
+                            signature: None,
+                        })
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await
+                .expect("Should join the task")
+                .expect("Should mutate the storage");
+        }
+        flush().await.expect("Should flush the storage");
+        // A failed mutation doesn't need a flush.
+        let generation = GENERATION.load(std::sync::atomic::Ordering::Acquire);
+        assert!(with_storage_mut(|_| Err::<(), _>(anyhow!("Refused")))
+            .await
+            .is_err());
+        assert_eq!(
+            GENERATION.load(std::sync::atomic::Ordering::Acquire),
+            generation
+        );
+
+        let reloaded =
+            json::Storage::new(&config().path(STORAGE_FILE)).expect("Should reload the storage");
+        let users = reloaded.private_users().unwrap();
+        assert_eq!(users.len(), USERS);
+        assert!(users
+            .iter()
+            .all(|u| u.points == POINTS_FIRST_VISIT + POINTS_MESSAGE));
+        assert_eq!(reloaded.message_count().unwrap(), USERS);
+        assert_eq!(reloaded.messages("hare").unwrap().len(), USERS);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
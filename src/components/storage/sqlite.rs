@@ -0,0 +1,244 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use flarch::nodeids::U256;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::events::Event;
+use super::{public_id, Message, StorageBackend, UserPrivate, Visit};
+
+/// Writes every change directly to an SQLite database.
+/// Users and messages are stored as their versioned JSON, so older rows are
+/// migrated the same way as in the JSON file.
+pub struct SqliteStorage {
+    // Connection isn't Sync, but the backend has to be.
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: &str) -> Result<Self> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS users (
+                id_private BLOB PRIMARY KEY,
+                id_public BLOB,
+                data TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                poi TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS messages_poi ON messages (poi, time);
+            CREATE TABLE IF NOT EXISTS visits (
+                user BLOB NOT NULL,
+                poi TEXT NOT NULL,
+                time INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS visits_user ON visits (user, time);",
+        )?;
+        Self::add_public_ids(&conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Databases from before the `id_public` column get it filled in.
+    fn add_public_ids(conn: &Connection) -> Result<()> {
+        let has_column: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('users') WHERE name = 'id_public'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_column {
+            conn.execute_batch("ALTER TABLE users ADD COLUMN id_public BLOB")?;
+            let ids = conn
+                .prepare("SELECT id_private FROM users")?
+                .query_map([], |row| row.get::<_, Vec<u8>>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for id in ids {
+                let id_private = U256::from(<[u8; 32]>::try_from(id.as_slice())?);
+                conn.execute(
+                    "UPDATE users SET id_public = ?1 WHERE id_private = ?2",
+                    params![public_id(&id_private).as_ref(), id],
+                )?;
+            }
+        }
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS users_public ON users (id_public)")?;
+        Ok(())
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow!("sqlite mutex poisoned"))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(rows: Vec<String>) -> Result<Vec<T>> {
+        rows.iter()
+            .map(|data| Ok(serde_json::from_str(data)?))
+            .collect()
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn user(&self, id: &U256) -> Result<Option<UserPrivate>> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT data FROM users WHERE id_private = ?1",
+                params![id.as_ref()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    fn user_by_public(&self, id_public: &U256) -> Result<Option<UserPrivate>> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT data FROM users WHERE id_public = ?1",
+                params![id_public.as_ref()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    fn put_user(&mut self, user: UserPrivate) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO users (id_private, id_public, data) VALUES (?1, ?2, ?3)",
+            params![
+                user.id_private.as_ref(),
+                user.public().as_ref(),
+                serde_json::to_string(&user)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn private_users(&self) -> Result<Vec<UserPrivate>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT data FROM users")?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Self::decode(rows)
+    }
+
+    fn push_message(&mut self, msg: Message) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO messages (poi, time, data) VALUES (?1, ?2, ?3)",
+            params![msg.poi, msg.time, serde_json::to_string(&msg)?],
+        )?;
+        Ok(())
+    }
+
+    fn messages(&self, poi: &str) -> Result<Vec<Message>> {
+        let conn = self.conn()?;
+        let mut stmt =
+            conn.prepare("SELECT data FROM messages WHERE poi = ?1 ORDER BY time, id")?;
+        let rows = stmt
+            .query_map(params![poi], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Self::decode(rows)
+    }
+
+    fn last_message(&self) -> Result<Option<Message>> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT data FROM messages ORDER BY time DESC, id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    fn message_count(&self) -> Result<usize> {
+        let count: i64 = self
+            .conn()?
+            .query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn push_visit(&mut self, visit: Visit) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO visits (user, poi, time) VALUES (?1, ?2, ?3)",
+            params![visit.user.as_ref(), visit.poi, visit.time],
+        )?;
+        Ok(())
+    }
+
+    fn visits(&self, user: &U256) -> Result<Vec<Visit>> {
+        let conn = self.conn()?;
+        let mut stmt =
+            conn.prepare("SELECT poi, time FROM visits WHERE user = ?1 ORDER BY time, rowid")?;
+        let visits = stmt
+            .query_map(params![user.as_ref()], |row| {
+                Ok(Visit {
+                    user: *user,
+                    poi: row.get(0)?,
+                    time: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(visits)
+    }
+
+    /// An event is applied with several writes, which must not be stored partially.
+    fn record(&mut self, event: Event) -> Result<()> {
+        self.conn()?.execute_batch("BEGIN")?;
+        match self.apply(&event) {
+            Ok(()) => {
+                self.conn()?.execute_batch("COMMIT")?;
+                Ok(())
+            }
+            Err(e) => {
+                self.conn()?.execute_batch("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_ids_of_old_databases() {
+        let dir = std::env::temp_dir().join(format!("warwick-sqlite-{}", U256::rnd()));
+        let path = dir.join("storage.sqlite").to_string_lossy().to_string();
+        fs::create_dir_all(&dir).unwrap();
+        let user = UserPrivate::new(U256::rnd(), "alice".into());
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE users (id_private BLOB PRIMARY KEY, data TEXT NOT NULL)")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO users (id_private, data) VALUES (?1, ?2)",
+            params![
+                user.id_private.as_ref(),
+                serde_json::to_string(&user).unwrap()
+            ],
+        )
+        .unwrap();
+        drop(conn);
+
+        let s = SqliteStorage::new(&path).unwrap();
+        let found = s.user_by_public(&user.public()).unwrap().unwrap();
+        assert_eq!(found.id_private, user.id_private);
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,120 @@
+//! The tile pack: map tiles around the POIs, downloaded once with `server tiles`, so the
+//! map also works where the campus Wi-Fi doesn't reach.
+
+use std::{fs, io::Read, path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract,
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::components::config::{config, OSM_TILE_SERVER};
+use crate::components::geo::BoundingBox;
+use crate::components::poi::load_catalog;
+
+/// The tile pack in the data directory, as `<z>/<x>/<y>.png`.
+pub const TILES_DIR: &str = "tiles";
+const MIN_ZOOM: u8 = 14;
+const DEFAULT_MAX_ZOOM: u8 = 18;
+/// Tiles are also downloaded for this margin around the POIs.
+const TILE_MARGIN_M: f64 = 300.;
+/// Keeps the load on the tile server reasonable.
+const MAX_TILES: usize = 5000;
+const DOWNLOAD_PAUSE: Duration = Duration::from_millis(200);
+
+fn tile_path(z: u8, x: u32, y: u32) -> String {
+    config().path(&format!("{TILES_DIR}/{z}/{x}/{y}.png"))
+}
+
+/// The configured tile server, if there is one.
+fn tile_server() -> Option<&'static str> {
+    let server = config().tile_server.trim_end_matches('/');
+    (!server.is_empty()).then_some(server)
+}
+
+fn tile_url(server: &str, z: u8, x: u32, y: u32) -> String {
+    format!("{server}/{z}/{x}/{y}.png")
+}
+
+/// Downloads the missing tiles around the POIs of the catalog from the configured
+/// tile server.
+pub fn run(args: &[String]) -> Result<()> {
+    let server = tile_server()
+        .filter(|&server| server != OSM_TILE_SERVER)
+        .ok_or(anyhow!(
+            "The OpenStreetMap tile servers don't allow bulk downloads: \
+            set tile_server or WARWICK_TILE_SERVER to one which does"
+        ))?;
+    let max_zoom = match args.first() {
+        Some(zoom) => zoom
+            .parse()
+            .map_err(|_| anyhow!("Usage: tiles [max-zoom]"))?,
+        None => DEFAULT_MAX_ZOOM,
+    };
+    let catalog = load_catalog()?;
+    let positions: Vec<_> = catalog.pois.iter().map(|p| p.position()).collect();
+    let bbox = BoundingBox::from_points(&positions)
+        .ok_or(anyhow!("There are no POIs to download tiles for"))?
+        .expand(TILE_MARGIN_M);
+    let tiles: Vec<(u8, u32, u32)> = (MIN_ZOOM..=max_zoom)
+        .flat_map(|z| bbox.tiles(z).into_iter().map(move |(x, y)| (z, x, y)))
+        .collect();
+    if tiles.len() > MAX_TILES {
+        return Err(anyhow!(
+            "{} tiles are too many, use a smaller max-zoom",
+            tiles.len()
+        ));
+    }
+
+    let mut downloaded = 0;
+    for &(z, x, y) in &tiles {
+        let path = tile_path(z, x, y);
+        if Path::new(&path).exists() {
+            continue;
+        }
+        let mut png = vec![];
+        ureq::get(&tile_url(server, z, x, y))
+            .set(
+                "User-Agent",
+                "warwick-pois tile pack (github.com/c4dt/rse_warwick)",
+            )
+            .call()?
+            .into_reader()
+            .read_to_end(&mut png)?;
+        if let Some(dir) = Path::new(&path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, png)?;
+        downloaded += 1;
+        std::thread::sleep(DOWNLOAD_PAUSE);
+    }
+    println!(
+        "{} tiles for zoom {MIN_ZOOM} to {max_zoom}, downloaded {downloaded}",
+        tiles.len()
+    );
+    Ok(())
+}
+
+/// Serves `/tiles/<z>/<x>/<y>.png` from the tile pack, and redirects to the configured
+/// tile server for tiles outside of it.
+pub async fn serve_tile(extract::Path((z, x, y)): extract::Path<(u8, u32, String)>) -> Response {
+    let Some(y) = y.strip_suffix(".png").and_then(|y| y.parse().ok()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match tokio::fs::read(tile_path(z, x, y)).await {
+        Ok(png) => (
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=604800"),
+            ],
+            png,
+        )
+            .into_response(),
+        Err(_) => match tile_server() {
+            Some(server) => Redirect::temporary(&tile_url(server, z, x, y)).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+    }
+}
@@ -4,19 +4,93 @@ use dioxus::prelude::*;
 
 /// Define a components module that contains all shared components for our app.
 mod components;
+use components::pages::{
+    About, Admin, Home, Layout, LeaderboardPage, Me, NotFound, PoiPage, UserPage,
+};
+#[cfg(feature = "server")]
+mod server;
 
 // We can import assets in dioxus with the `asset!` macro. This macro takes a path to an asset relative to the crate root.
 // The macro returns an `Asset` type that will display as the path to the asset in the browser or a local path in desktop bundles.
 const FAVICON: Asset = asset!("/assets/favicon.ico");
 // The asset macro also minifies some assets like CSS and JS to make bundled smaller
 const MAIN_CSS: Asset = asset!("/assets/styling/main.css");
+/// Used by the web app manifest served in `server.rs`.
+pub const APP_ICON: Asset = asset!("/assets/icons/app.svg");
 
 fn main() {
+    // Called with a command, the server runs it instead of serving the app.
+    #[cfg(feature = "server")]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if let Some((command, args)) = args.split_first() {
+            if let Err(e) = run_command(command, args) {
+                eprintln!("{command} failed: {e:?}");
+                eprintln!("{USAGE}");
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    // The server runs its own axum router to flush the storage on shutdown.
+    #[cfg(feature = "server")]
+    server::serve(App);
+
     // The `launch` function is the main entry point for a dioxus app. It takes a component and renders it with the platform feature
     // you have enabled
+    #[cfg(not(feature = "server"))]
     dioxus::launch(App);
 }
 
+#[cfg(feature = "server")]
+const USAGE: &str = "Usage: server [command]
+Without a command, the server serves the app. The commands are:
+  import <export-file> [catalog.json] [--dry-run]
+  check-storage <file>...
+  verify-messages <file>...
+  tiles [max-zoom]";
+
+#[cfg(feature = "server")]
+fn run_command(command: &str, args: &[String]) -> anyhow::Result<()> {
+    match command {
+        // Seeds the POI catalog from an OpenStreetMap export.
+        "import" => components::import::run(args),
+        // Makes sure older storage files still load.
+        "check-storage" => components::storage::check(args),
+        // Checks the signatures of exported messages.
+        "verify-messages" => components::identity::verify_exports(args),
+        // Downloads the map tiles around the POIs for offline use.
+        "tiles" => components::tiles::run(args),
+        _ => Err(anyhow::anyhow!("Unknown command")),
+    }
+}
+
+/// The pages of the app. The fullstack server renders every route, so links to them
+/// also work on reload.
+#[derive(Routable, Clone, PartialEq, Debug)]
+#[rustfmt::skip]
+pub enum Route {
+    #[layout(Layout)]
+        #[route("/")]
+        Home {},
+        #[route("/poi/:id")]
+        PoiPage { id: String },
+        #[route("/leaderboard")]
+        LeaderboardPage {},
+        #[route("/me")]
+        Me {},
+        #[route("/user/:id")]
+        UserPage { id: String },
+        #[route("/about")]
+        About {},
+        #[route("/admin")]
+        Admin {},
+    #[end_layout]
+    #[route("/:..route")]
+    NotFound { route: Vec<String> },
+}
+
 /// App is the main component of our app. Components are the building blocks of dioxus apps. Each component is a function
 /// that takes some props and returns an Element. In this case, App takes no props because it is the root of our app.
 ///
@@ -29,7 +103,10 @@ fn App() -> Element {
         // we are using the `document::Link` component to add a link to our favicon and main CSS file into the head of our app.
         document::Link { rel: "icon", href: FAVICON }
         document::Link { rel: "stylesheet", href: MAIN_CSS }
+        document::Link { rel: "manifest", href: "/manifest.json" }
+        document::Link { rel: "apple-touch-icon", href: APP_ICON }
+        document::Meta { name: "theme-color", content: "#0f1116" }
 
-        crate::components::map::MapPOI{}
+        Router::<Route> {}
     }
 }
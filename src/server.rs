@@ -0,0 +1,103 @@
+//! Runs the fullstack server ourselves instead of through `dioxus::launch`, so the
+//! storage can be flushed when the server shuts down.
+
+use axum::{http::header, response::IntoResponse, routing::get};
+use dioxus::{logger::tracing, prelude::*};
+
+use crate::components::{config, session, storage, tiles};
+
+/// Served from the root, so it can control all pages.
+const SERVICE_WORKER: &str = include_str!("../assets/sw.js");
+
+pub fn serve(app: fn() -> Element) {
+    tokio::runtime::Runtime::new()
+        .expect("Should create runtime")
+        .block_on(async move {
+            dioxus::logger::initialize_default();
+            if let Err(e) = config::init() {
+                tracing::error!("Refusing to start: {e:?}");
+                std::process::exit(1);
+            }
+            if let Err(e) = session::init() {
+                tracing::error!("Refusing to start: {e:?}");
+                std::process::exit(1);
+            }
+            if let Err(e) = storage::init().await {
+                tracing::error!("Refusing to start: {e:?}");
+                std::process::exit(1);
+            }
+            let address = dioxus::cli_config::fullstack_address_or_localhost();
+            let router = axum::Router::new()
+                .route("/sw.js", get(service_worker))
+                .route("/manifest.json", get(manifest))
+                .route("/tiles/:z/:x/:y", get(tiles::serve_tile))
+                .serve_dioxus_application(ServeConfig::new().expect("Should load index"), app);
+            let listener = tokio::net::TcpListener::bind(address)
+                .await
+                .expect("Should bind address");
+            if let Err(e) = axum::serve(listener, router.into_make_service())
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+            {
+                tracing::error!("Server error: {e:?}");
+            }
+            tracing::info!("Flushing storage before exiting");
+            if let Err(e) = storage::flush().await {
+                tracing::error!("Couldn't write storage: {e:?}");
+            }
+        });
+}
+
+async fn service_worker() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/javascript"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        SERVICE_WORKER,
+    )
+}
+
+/// The web app manifest, which makes the app installable.
+async fn manifest() -> impl IntoResponse {
+    let settings = &config::config().settings;
+    let manifest = serde_json::json!({
+        "name": settings.event_name,
+        "short_name": settings.event_name,
+        "start_url": "/",
+        "display": "standalone",
+        "background_color": "#0f1116",
+        "theme_color": "#0f1116",
+        "icons": [{
+            "src": crate::APP_ICON.to_string(),
+            "sizes": "any",
+            "type": "image/svg+xml",
+        }],
+    });
+    (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        manifest.to_string(),
+    )
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Should install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Should install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}